@@ -0,0 +1,98 @@
+//! Benchmarks that run on stable Rust: `cargo bench`. Each one reports the mean time per call
+//! over about a second, after a short warm-up.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use tangle_rs::bitboards::*;
+use tangle_rs::consts::*;
+use tangle_rs::gamestate::Game;
+
+const SQUARES: u64 = 1 << 63 | 1 << 50 | 1 << 25 | 1 << 5;
+const SQUARE_SUM: u32 = 63 + 50 + 25 + 5;
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    let warm_up = Instant::now();
+    let mut batch = 0u64;
+    while warm_up.elapsed() < Duration::from_millis(100) {
+        f();
+        batch += 1;
+    }
+
+    let start = Instant::now();
+    let mut iterations = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        for _ in 0..batch {
+            f();
+        }
+        iterations += batch;
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / iterations as f64;
+    println!("{name:<20} {nanos:>12.1} ns/iter");
+}
+
+/// Ways of visiting the set bits of a bitboard.
+fn bit_scans() {
+    bench("find_while", || {
+        let mut b = black_box(SQUARES);
+        let (mut idx, mut i) = (0, 0);
+        while b != 0 {
+            if b & 1 != 0 {
+                idx += i;
+            }
+            b >>= 1;
+            i += 1;
+        }
+        assert_eq!(idx, SQUARE_SUM);
+    });
+    bench("find_leading", || {
+        let mut b = black_box(SQUARES);
+        let (mut idx, mut sha) = (0, 0);
+        while b != 0 {
+            let lz = b.leading_zeros();
+            idx += u64::BITS - lz - 1 - sha;
+            sha += lz + 1;
+            b <<= lz + 1;
+        }
+        assert_eq!(idx, SQUARE_SUM);
+    });
+    bench("find_trailing", || {
+        let mut b = black_box(SQUARES);
+        let (mut idx, mut sha) = (0, 0);
+        while b != 0 {
+            let tz = b.trailing_zeros();
+            idx += tz + sha;
+            sha += tz + 1;
+            b >>= tz + 1;
+        }
+        assert_eq!(idx, SQUARE_SUM);
+    });
+    bench("find_trailing_kill", || {
+        let mut b = black_box(SQUARES);
+        let mut idx = 0;
+        while b != 0 {
+            let tz = b.trailing_zeros();
+            idx += tz;
+            b ^= 1 << tz;
+        }
+        assert_eq!(idx, SQUARE_SUM);
+    });
+    bench("ms1b", || {
+        black_box(ms1b(black_box(1 << 50)));
+    });
+    bench("shift", || {
+        black_box(shift::<N, ONES>(black_box(1 << 63)));
+    });
+}
+
+fn main() {
+    bit_scans();
+
+    let mut game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    bench("legal_moves", || game.calc_legal_moves());
+    let kiwipete =
+        Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+    bench("perft_kiwipete_2", || {
+        assert_eq!(kiwipete.perft(black_box(2)), 2039);
+    });
+}
@@ -1,11 +1,11 @@
 import UIBoard from "./ui_board.js";
-import init, { Game } from "../pkg/tangle_rs.js";
+import init, { GameTree } from "../pkg/tangle_rs.js";
 
 async function run() {
 
     await init();
 
-    let board = new Game("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    let board = new GameTree("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
     let ui_board = new UIBoard(board);
     // board.legal_moves;
     ui_board.draw();
@@ -20,6 +20,19 @@ async function run() {
         ui_board.drawArrows();
     });
 
+    // browse the move tree: left and right step through the line, home and end jump
+    document.addEventListener("keydown", (e) => {
+        const moved = {
+            ArrowLeft: () => board.back(),
+            ArrowRight: () => board.forward(),
+            Home: () => { board.to_start(); return true },
+            End: () => { board.to_end(); return true },
+        }[e.key];
+        if (moved && moved()) {
+            ui_board.redraw();
+        }
+    });
+
     window.globs = { board: board, uiboard: ui_board }
 }
 
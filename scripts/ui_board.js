@@ -1,8 +1,11 @@
+import { Piece } from "../pkg/tangle_rs.js";
+
 const SQUARE_SIZE = 80;
 const PIECE_SIZE = 70;
 const TILE_OFFSET = (SQUARE_SIZE - PIECE_SIZE) / 2;
 const getTilesRect = () => document.getElementById("tiles").getBoundingClientRect()
 const idxToSquare = (idx) => { return { row: Math.floor(idx / 8), col: idx % 8 } }
+const pieceName = (piece) => Piece[piece].toLowerCase()
 
 export class UIBoard {
     constructor(state) {
@@ -16,6 +19,16 @@ export class UIBoard {
         JSON.parse(this.state.send_board()).map(([square, piece]) => this.addPiece(piece.kind, piece.color, square));
         this.drawArrows();
     }
+    // after jumping to another position, rather than playing a move
+    redraw() {
+        for (let idx in this.squares) {
+            this.removePiece(idx);
+        }
+        const square = (idx) => this.flipped ? 63 - idx : idx;
+        JSON.parse(this.state.send_board()).map(([idx, piece]) => this.addPiece(piece.kind, piece.color, square(idx)));
+        this.clearArrows();
+        this.drawArrows();
+    }
     get(idx) {
         return this.squares[idx];
     }
@@ -135,35 +148,30 @@ export class UIBoard {
             from = 63 - from;
             to = 63 - to;
         }
-        let [ok, response] = JSON.parse(this.state.input_move(from, to, promotion));
-        if (!ok) { return false };
-        for (let update of response.updates) {
-            let [f, t] = update;
-            if (this.flipped) {
-                [f, t] = [63 - f, t < 0 ? t : 63 - t];
-            }
-            if (t < 0) {
-                this.removePiece(f);
-            } else {
-                this.move(f, t);
-            }
+        let result = this.state.input_move(from, to, promotion);
+        if (!result) { return false };
+        const square = (idx) => this.flipped ? 63 - idx : idx;
+        if (result.captured_square !== undefined) {
+            this.removePiece(square(result.captured_square));
         }
-        if (promotion > 0) {
-            let color = this.get(to).classList[1];
-            switch (promotion) {
-                case 1:
-                    this.addPiece("queen", color, to);
-                    break;
-                case 2:
-                    this.addPiece("rook", color, to);
-                    break;
-                case 3:
-                    this.addPiece("bishop", color, to);
-                    break;
-                case 4:
-                    this.addPiece("knight", color, to);
-                    break;
-            }
+        // lift the king and the rook before dropping either: Chess960 castling may swap them
+        let piece = this.get(square(result.from));
+        delete this.squares[square(result.from)];
+        let rook;
+        if (result.rook_from !== undefined) {
+            rook = this.get(square(result.rook_from));
+            delete this.squares[square(result.rook_from)];
+        }
+        this.put(piece, square(result.to));
+        if (rook) {
+            this.put(rook, square(result.rook_to));
+        }
+        if (result.promotion) {
+            this.addPiece(pieceName(result.promotion), piece.classList[1], square(result.to));
+        }
+        // removals come last: an atomic capture blows up the piece that just landed
+        for (let idx of result.removed) {
+            this.removePiece(square(idx));
         }
         this.clearArrows()
         this.drawArrows()
@@ -192,11 +200,11 @@ export class UIBoard {
     }
     async may_promote(from, to) {
         let piece = this.get(from);
-        if (piece.classList.contains("pawn") && (to < 8 || to > 56)) {
-            const choice = await promotion_dialog(piece.classList[1]);
+        if (piece.classList.contains("pawn") && (to < 8 || to >= 56)) {
+            const choice = await promotion_dialog(piece.classList[1], this.state.variant() == "antichess");
             return choice
         };
-        return 0;
+        return undefined;
     }
     get legal_moves() {
         let moves = this.state.get_legal_moves();
@@ -215,7 +223,7 @@ export class UIBoard {
     }
 }
 
-function promotion_dialog(color) {
+function promotion_dialog(color, kings) {
     return new Promise((resolve) => {
         const dialog = document.createElement('div');
         dialog.style.position = 'fixed';
@@ -232,26 +240,14 @@ function promotion_dialog(color) {
         let board = document.getElementById("chessboard");
         board.appendChild(dialog);
 
-        let queen = document.createElement("img")
-        queen.src = "assets/" + "queen" + "_" + color + ".svg";
-        queen.addEventListener("click", () => { board.removeChild(dialog), resolve(1) })
-
-        let rook = document.createElement("img")
-        rook.src = "assets/" + "rook" + "_" + color + ".svg";
-        rook.addEventListener("click", () => { board.removeChild(dialog); resolve(2) })
-
-        let bishop = document.createElement("img")
-        bishop.src = "assets/" + "bishop" + "_" + color + ".svg";
-        bishop.addEventListener("click", () => { board.removeChild(dialog), resolve(3) })
-
-        let knight = document.createElement("img")
-        knight.src = "assets/" + "knight" + "_" + color + ".svg";
-        knight.addEventListener("click", () => { board.removeChild(dialog); resolve(4) })
-
-        dialog.appendChild(queen);
-        dialog.appendChild(rook);
-        dialog.appendChild(bishop);
-        dialog.appendChild(knight);
+        let pieces = ["queen", "rook", "bishop", "knight"];
+        if (kings) { pieces.push("king") }
+        for (let name of pieces) {
+            let img = document.createElement("img")
+            img.src = "assets/" + name + "_" + color + ".svg";
+            img.addEventListener("click", () => { board.removeChild(dialog); resolve(Piece[name[0].toUpperCase() + name.slice(1)]) })
+            dialog.appendChild(img);
+        }
     });
 }
 
@@ -0,0 +1,92 @@
+//! Runs an EPD test suite:
+//! `epd <suite.epd> [--depth N] [--movetime MS] [--threads N] [--multipv N] [--eval-file NET]`.
+//! Each position is searched to the given depth, or for one second by default, with the
+//! handcrafted evaluation unless a network is given. With `--multipv`, the best lines of each
+//! position follow its result as UCI `info` lines.
+
+use std::time::Duration;
+
+use tangle_rs::epd::Epd;
+use tangle_rs::nnue::Network;
+use tangle_rs::search::{Engine, Limits};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut limits = Limits::default();
+    let mut engine = Engine::new();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or_else(|| panic!("{arg} needs a number"))
+        };
+        match arg.as_str() {
+            "--depth" => limits.depth = Some(value() as u32),
+            "--movetime" => limits.movetime = Some(Duration::from_millis(value())),
+            "--threads" => {
+                engine.set_option("Threads", &value().to_string());
+            }
+            "--multipv" => {
+                engine.set_option("MultiPV", &value().to_string());
+            }
+            "--eval-file" => {
+                let file = args.next().expect("--eval-file needs a path");
+                let network = Network::open(&file).unwrap_or_else(|e| panic!("{file}: {e}"));
+                engine.set_network(Some(network));
+            }
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.expect(
+        "usage: epd <suite.epd> [--depth N] [--movetime MS] [--threads N] [--multipv N] \
+         [--eval-file NET]",
+    );
+    if limits.depth.is_none() && limits.movetime.is_none() {
+        limits.movetime = Some(Duration::from_secs(1));
+    }
+
+    let suite = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"));
+    let (mut passed, mut total) = (0, 0);
+    for (number, line) in suite.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(epd) = Epd::parse(line) else {
+            eprintln!("line {}: not an EPD record", number + 1);
+            continue;
+        };
+        let game = epd.game();
+        engine.clear();
+        let result = engine.search(&game, limits);
+        let solved = result
+            .best_move
+            .is_some_and(|played| epd.solved_by(&game, played));
+        let played = result
+            .best_move
+            .map_or("-".to_string(), |(from, to, promotion)| {
+                game.uci_move(from, to, promotion)
+            });
+        let expected = if epd.best_moves.is_empty() {
+            format!("am {}", epd.avoid_moves.join(" "))
+        } else {
+            format!("bm {}", epd.best_moves.join(" "))
+        };
+        println!(
+            "{:<12} {} {played:<6} {expected:<16} depth {} score {} nodes {}",
+            epd.id.as_deref().unwrap_or(&format!("#{}", number + 1)),
+            if solved { "pass" } else { "FAIL" },
+            result.depth,
+            result.score,
+            result.nodes,
+        );
+        if engine.multipv() > 1 {
+            for info in result.uci_info(&game) {
+                println!("  {info}");
+            }
+        }
+        passed += solved as u32;
+        total += 1;
+    }
+    println!("passed {passed}/{total}");
+}
@@ -0,0 +1,112 @@
+//! Plays two engine configurations against each other and runs an SPRT on the results:
+//! `selfplay <openings> --new SPEC --base SPEC [--games N] [--elo0 E] [--elo1 E]
+//! [--alpha A] [--beta B] [--variant NAME] [--pgn FILE]`.
+//! Openings are full FENs, one per line, each played twice with colors swapped. A SPEC is
+//! what `Player::parse` takes, such as `depth=6,Hash=64`. The test stops at a verdict or
+//! after the given number of games. Variants with drops, such as Crazyhouse, are refused.
+
+use std::fs::File;
+use std::io::Write;
+
+use tangle_rs::gamestate::Game;
+use tangle_rs::letterbox::Color;
+use tangle_rs::selfplay::{play, Player};
+use tangle_rs::sprt::{elo, Sprt, Verdict};
+use tangle_rs::variant::Outcome;
+
+const USAGE: &str = "usage: selfplay <openings> --new SPEC --base SPEC [--games N] [--elo0 E] \
+                     [--elo1 E] [--alpha A] [--beta B] [--variant NAME] [--pgn FILE]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (mut path, mut new, mut base, mut variant, mut pgn) = (None, None, None, None, None);
+    let mut games = 1000;
+    let mut sprt = Sprt {
+        elo0: 0.0,
+        elo1: 5.0,
+        alpha: 0.05,
+        beta: 0.05,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| panic!("{arg} needs a value"));
+        let number = |v: String| {
+            v.parse::<f64>()
+                .unwrap_or_else(|_| panic!("{v} is no number"))
+        };
+        match arg.as_str() {
+            "--new" => new = Some(value()),
+            "--base" => base = Some(value()),
+            "--games" => games = number(value()) as usize,
+            "--elo0" => sprt.elo0 = number(value()),
+            "--elo1" => sprt.elo1 = number(value()),
+            "--alpha" => sprt.alpha = number(value()),
+            "--beta" => sprt.beta = number(value()),
+            "--variant" => variant = Some(value()),
+            "--pgn" => pgn = Some(value()),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.expect(USAGE);
+    let new = Player::parse("new", &new.expect(USAGE)).unwrap_or_else(|e| panic!("--new: {e}"));
+    let base = Player::parse("base", &base.expect(USAGE)).unwrap_or_else(|e| panic!("--base: {e}"));
+    let mut pgn = pgn.map(|path| File::create(&path).unwrap_or_else(|e| panic!("{path}: {e}")));
+
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"));
+    let openings: Vec<Game> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|fen| Game::new(fen, variant.clone()).unwrap_or_else(|e| panic!("{path}: {e}")))
+        .collect();
+    assert!(!openings.is_empty(), "{path}: no openings");
+
+    let (lower, upper) = sprt.bounds();
+    let (mut wins, mut draws, mut losses) = (0, 0, 0);
+    let mut verdict = None;
+    for round in 0..games {
+        let opening = &openings[round / 2 % openings.len()];
+        let new_color = if round % 2 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let played = match new_color {
+            Color::White => play(opening, &new, &base),
+            Color::Black => play(opening, &base, &new),
+        };
+        let played = match played {
+            Ok(played) => played,
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        };
+        match played.outcome {
+            Some(Outcome::Winner(color)) if color == new_color => wins += 1,
+            Some(Outcome::Winner(_)) => losses += 1,
+            Some(Outcome::Draw) => draws += 1,
+            None => eprintln!("round {}: {}", round + 1, played.termination),
+        }
+        if let Some(file) = &mut pgn {
+            file.write_all(played.to_pgn(round + 1).as_bytes())
+                .expect("cannot write the PGN");
+        }
+        println!(
+            "{:>5} games  +{wins} ={draws} -{losses}  elo {:+.1}  llr {:.2} ({:.2}, {:.2})",
+            round + 1,
+            elo(wins, draws, losses),
+            sprt.llr(wins, draws, losses),
+            lower,
+            upper,
+        );
+        verdict = sprt.verdict(wins, draws, losses);
+        if verdict.is_some() {
+            break;
+        }
+    }
+    match verdict {
+        Some(Verdict::H1) => println!("H1 accepted: new gains at least {} elo", sprt.elo1),
+        Some(Verdict::H0) => println!("H0 accepted: new gains at most {} elo", sprt.elo0),
+        None => println!("no verdict after {games} games"),
+    }
+}
@@ -0,0 +1,72 @@
+//! Tunes the evaluation weights on labelled positions:
+//! `tune <positions> [--iterations N] [--rate R] [--json] [--output FILE]`.
+//! Each line of the positions file is a FEN followed by the game result. The tuned weights
+//! are written as Rust source for `eval.rs`, or as JSON.
+
+use tangle_rs::eval::DEFAULT_PARAMS;
+use tangle_rs::tune::{best_k, error, to_json, to_rust, Sample, Tuner};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (mut path, mut output, mut json) = (None, None, false);
+    let (mut iterations, mut rate) = (1000, 1.0);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--iterations" => {
+                iterations = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .expect("--iterations needs a number")
+            }
+            "--rate" => {
+                rate = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .expect("--rate needs a number")
+            }
+            "--json" => json = true,
+            "--output" => output = Some(args.next().expect("--output needs a path")),
+            _ => path = Some(arg),
+        }
+    }
+    let path =
+        path.expect("usage: tune <positions> [--iterations N] [--rate R] [--json] [--output FILE]");
+
+    let data = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"));
+    let mut samples = vec![];
+    for (number, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match Sample::parse(line) {
+            Some(sample) => samples.push(sample),
+            None => eprintln!("{path}:{}: no result, skipped", number + 1),
+        }
+    }
+    let k = best_k(&samples, &DEFAULT_PARAMS);
+    eprintln!(
+        "{} positions, k = {k:.2}, error {:.6}",
+        samples.len(),
+        error(&samples, &DEFAULT_PARAMS, k)
+    );
+
+    let tuner = Tuner {
+        k,
+        iterations,
+        rate,
+    };
+    let params = tuner.tune(&samples, &DEFAULT_PARAMS, |iteration, error| {
+        eprintln!("iteration {iteration}: error {error:.6}")
+    });
+    eprintln!("tuned error {:.6}", error(&samples, &params, k));
+
+    let text = if json {
+        to_json(&params)
+    } else {
+        to_rust(&params)
+    };
+    match output {
+        Some(output) => std::fs::write(&output, text).unwrap_or_else(|e| panic!("{output}: {e}")),
+        None => print!("{text}"),
+    }
+}
@@ -1,6 +1,8 @@
 use serde::ser::{SerializeTuple, Serializer};
 use serde::Serialize;
 
+use crate::letterbox::{unpack, Color, Piece, EMPTY};
+
 #[inline(always)]
 pub fn bsf(bb: u64) -> u32 {
     tzc(bb)
@@ -61,7 +63,7 @@ macro_rules! apply {
 }
 pub(crate) use apply;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BitBoards {
     pub pawns: u64,
     pub knights: u64,
@@ -71,6 +73,8 @@ pub struct BitBoards {
     pub kings: u64,
     pub whites: u64,
     pub blacks: u64,
+    /// `Piece | Color` of every square, kept in step with the bitboards.
+    pub mailbox: [u8; 64],
 }
 
 impl Serialize for BitBoards {
@@ -79,84 +83,51 @@ impl Serialize for BitBoards {
         S: Serializer,
     {
         #[derive(Serialize)]
-        struct Piece<'a> {
-            kind: &'a str,
-            color: &'a str,
+        struct Square {
+            kind: Piece,
+            color: Color,
         }
 
         let mut ser = serializer.serialize_tuple(64)?;
-        apply!(self.pawns & self.whites, i -> {
-            ser.serialize_element(&(i, Piece { kind: "pawn", color: "white"}))?;
-        });
-        apply!(self.knights & self.whites, i -> {
-            ser.serialize_element(&(i, Piece { kind: "knight", color: "white"}))?;
-        });
-        apply!(self.bishops & self.whites, i -> {
-            ser.serialize_element(&(i, Piece { kind: "bishop", color: "white"}))?;
-        });
-        apply!(self.rooks & self.whites, i -> {
-            ser.serialize_element(&(i, Piece { kind: "rook", color: "white"}))?;
-        });
-        apply!(self.queens & self.whites, i -> {
-            ser.serialize_element(&(i, Piece { kind: "queen", color: "white"}))?;
-        });
-        apply!(self.kings & self.whites, i -> {
-            ser.serialize_element(&(i, Piece { kind: "king", color: "white"}))?;
-        });
-        apply!(self.pawns & self.blacks, i -> {
-            ser.serialize_element(&(i, Piece { kind: "pawn", color: "black"}))?;
-        });
-        apply!(self.knights & self.blacks, i -> {
-            ser.serialize_element(&(i, Piece { kind: "knight", color: "black"}))?;
-        });
-        apply!(self.bishops & self.blacks, i -> {
-            ser.serialize_element(&(i, Piece { kind: "bishop", color: "black"}))?;
-        });
-        apply!(self.rooks & self.blacks, i -> {
-            ser.serialize_element(&(i, Piece { kind: "rook", color: "black"}))?;
-        });
-        apply!(self.queens & self.blacks, i -> {
-            ser.serialize_element(&(i, Piece { kind: "queen", color: "black"}))?;
-        });
-        apply!(self.kings & self.blacks, i -> {
-            ser.serialize_element(&(i, Piece { kind: "king", color: "black"}))?;
+        apply!(self.whites | self.blacks, i -> {
+            let (kind, color) = self.piece_at(i).unwrap();
+            ser.serialize_element(&(i, Square { kind, color }))?;
         });
         ser.end()
     }
 }
 
 impl BitBoards {
+    pub const EMPTY: BitBoards = BitBoards {
+        whites: 0,
+        blacks: 0,
+        pawns: 0,
+        rooks: 0,
+        bishops: 0,
+        knights: 0,
+        queens: 0,
+        kings: 0,
+        mailbox: [EMPTY; 64],
+    };
+
     pub fn from_fen(position: &str) -> Self {
-        let mut bitboard = BitBoards {
-            whites: 0,
-            blacks: 0,
-            pawns: 0,
-            rooks: 0,
-            bishops: 0,
-            knights: 0,
-            queens: 0,
-            kings: 0,
-        };
+        let mut bitboard = BitBoards::EMPTY;
         position.split('/').enumerate().for_each(|(rank, pieces)| {
             let mut file: usize = 0;
             pieces.chars().for_each(|c| {
-                if let Some(blanks) = c.to_digit(10) {
+                if c == '~' {
+                    // promoted piece marker (Crazyhouse)
+                } else if let Some(blanks) = c.to_digit(10) {
                     file += blanks as usize;
                 } else {
-                    if c.is_uppercase() {
-                        bitboard.whites |= 1 << (rank * 8 + file);
+                    let color = if c.is_uppercase() {
+                        Color::White
                     } else {
-                        bitboard.blacks |= 1 << (rank * 8 + file);
-                    };
-                    match c.to_ascii_lowercase() {
-                        'p' => bitboard.pawns |= 1 << (rank * 8 + file),
-                        'n' => bitboard.knights |= 1 << (rank * 8 + file),
-                        'b' => bitboard.bishops |= 1 << (rank * 8 + file),
-                        'r' => bitboard.rooks |= 1 << (rank * 8 + file),
-                        'q' => bitboard.queens |= 1 << (rank * 8 + file),
-                        'k' => bitboard.kings |= 1 << (rank * 8 + file),
-                        _ => {}
+                        Color::Black
                     };
+                    if let Some(piece) = Piece::from_letter(c) {
+                        bitboard.put(rank * 8 + file, piece, color);
+                    }
                     file += 1;
                 }
             })
@@ -164,111 +135,128 @@ impl BitBoards {
         bitboard
     }
 
-    pub fn get_color_bb_mut(&mut self, turn: bool) -> &mut u64 {
-        if turn {
-            return &mut self.whites;
+    pub fn piece_at(&self, square: usize) -> Option<(Piece, Color)> {
+        unpack(self.mailbox[square])
+    }
+
+    /// Puts a piece on an empty square.
+    pub fn put(&mut self, square: usize, piece: Piece, color: Color) {
+        debug_assert_eq!(self.mailbox[square], EMPTY, "square {square} is taken");
+        *self.pieces_mut(piece) |= 1 << square;
+        *self.color_mut(color) |= 1 << square;
+        self.mailbox[square] = piece | color;
+    }
+
+    /// Takes the piece off `square`, if there is one.
+    pub fn remove(&mut self, square: usize) -> Option<(Piece, Color)> {
+        let (piece, color) = self.piece_at(square)?;
+        *self.pieces_mut(piece) &= !(1 << square);
+        *self.color_mut(color) &= !(1 << square);
+        self.mailbox[square] = EMPTY;
+        Some((piece, color))
+    }
+
+    /// Whether the mailbox and the bitboards describe the same position.
+    pub fn is_consistent(&self) -> bool {
+        let mut rebuilt = BitBoards::EMPTY;
+        for (square, &code) in self.mailbox.iter().enumerate() {
+            if let Some((piece, color)) = unpack(code) {
+                rebuilt.put(square, piece, color);
+            }
         }
-        &mut self.blacks
+        rebuilt == *self
     }
 
-    pub fn get_piece_bb_mut(&mut self, bb: u64) -> &mut u64 {
-        if self.pawns & bb != 0 {
-            &mut self.pawns
-        } else if self.knights & bb != 0 {
-            &mut self.knights
-        } else if self.bishops & bb != 0 {
-            &mut self.bishops
-        } else if self.rooks & bb != 0 {
-            &mut self.rooks
-        } else if self.queens & bb != 0 {
-            &mut self.queens
-        } else if self.kings & bb != 0 {
-            &mut self.kings
-        } else {
-            panic!("Given bitboard does not match any piece bitboard.");
+    pub fn pieces(&self, piece: Piece) -> u64 {
+        match piece {
+            Piece::Pawn => self.pawns,
+            Piece::Knight => self.knights,
+            Piece::Bishop => self.bishops,
+            Piece::Rook => self.rooks,
+            Piece::Queen => self.queens,
+            Piece::King => self.kings,
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::simd::{
-        cmp::{SimdOrd, SimdPartialEq, SimdPartialOrd},
-        u8x4, Mask, Simd, SimdElement,
-    };
-    use test::Bencher;
+    pub fn pieces_mut(&mut self, piece: Piece) -> &mut u64 {
+        match piece {
+            Piece::Pawn => &mut self.pawns,
+            Piece::Knight => &mut self.knights,
+            Piece::Bishop => &mut self.bishops,
+            Piece::Rook => &mut self.rooks,
+            Piece::Queen => &mut self.queens,
+            Piece::King => &mut self.kings,
+        }
+    }
 
-    #[bench]
-    fn find_while(b: &mut Bencher) {
-        b.iter(|| {
-            let mut idx = 0;
-            let mut b: u64 = 1 << 63 | 1 << 50 | 1 << 25 | 1 << 5;
-            let mut i = 0;
-            while b != 0 {
-                if b & 1 != 0 {
-                    idx += i;
+    /// Piece placement field of a FEN, with `~` after the pieces in `promoted`.
+    pub fn to_fen(&self, promoted: u64) -> String {
+        let mut fen = String::new();
+        for rank in 0..8 {
+            let mut blanks = 0;
+            for file in 0..8 {
+                let square = rank * 8 + file;
+                match self.piece_at(square) {
+                    Some((piece, color)) => {
+                        if blanks > 0 {
+                            fen.push_str(&blanks.to_string());
+                            blanks = 0;
+                        }
+                        if color == Color::White {
+                            fen.push(piece.letter().to_ascii_uppercase());
+                        } else {
+                            fen.push(piece.letter());
+                        }
+                        if promoted & 1 << square != 0 {
+                            fen.push('~');
+                        }
+                    }
+                    None => blanks += 1,
                 }
-                b >>= 1;
-                i += 1;
             }
-            assert_eq!(idx, 63 + 50 + 25 + 5);
-        });
-    }
-    #[bench]
-    fn find_leading(b: &mut Bencher) {
-        b.iter(|| {
-            let mut b: u64 = 1 << 63 | 1 << 50 | 1 << 25 | 1 << 5;
-            let mut idx = 0;
-            let mut sha = 0;
-            while b != 0 {
-                let lz = b.leading_zeros();
-                idx += u64::BITS - lz - 1 - sha;
-                sha += lz + 1;
-                b <<= lz + 1;
+            if blanks > 0 {
+                fen.push_str(&blanks.to_string());
             }
-            assert_eq!(idx, 63 + 50 + 25 + 5);
-        })
-    }
-    #[bench]
-    fn find_trailing(b: &mut Bencher) {
-        b.iter(|| {
-            let mut b: u64 = 1 << 63 | 1 << 50 | 1 << 25 | 1 << 5;
-            let mut idx = 0;
-            let mut sha = 0;
-            while b != 0 {
-                let tz = b.trailing_zeros();
-                idx += tz + sha;
-                sha += tz + 1;
-                b >>= tz + 1;
+            if rank < 7 {
+                fen.push('/');
             }
-            assert_eq!(idx, 63 + 50 + 25 + 5);
-        })
+        }
+        fen
     }
-    #[bench]
-    fn find_trailing_kill(b: &mut Bencher) {
-        b.iter(|| {
-            let mut b: u64 = 1 << 63 | 1 << 50 | 1 << 25 | 1 << 5;
-            let mut idx = 0;
-            while b != 0 {
-                let tz = b.trailing_zeros();
-                idx += tz;
-                b ^= 1 << tz;
-            }
-            assert_eq!(idx, 63 + 50 + 25 + 5);
-        })
+
+    pub fn color(&self, color: Color) -> u64 {
+        match color {
+            Color::White => self.whites,
+            Color::Black => self.blacks,
+        }
     }
+
+    pub fn color_mut(&mut self, color: Color) -> &mut u64 {
+        match color {
+            Color::White => &mut self.whites,
+            Color::Black => &mut self.blacks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
     #[test]
     fn tst() {
-        let mut b: u64 = 1 << 63 | 1 << 50 | 1 << 25 | 1 << 5;
+        let b: u64 = 1 << 63 | 1 << 50 | 1 << 25 | 1 << 5;
         let i = b.trailing_zeros();
         println!("{:064b}", b);
         println!("{:064b}", b ^ (1 << i));
         println!("{i} {}", (b ^ (1 << i)).trailing_zeros());
     }
 
+    #[cfg(feature = "nightly")]
     #[test]
     fn it_simd() {
+        use std::simd::{cmp::SimdPartialEq, u8x4, Mask};
+
         let a = u8x4::from_array([0b01000001, 0b00010001, 0b01010000, 0b00001100]);
         let mask = u8x4::splat(0b01000001_u8);
         println!("simd: {:?}", (a & mask).simd_eq(mask));
@@ -278,13 +266,21 @@ mod tests {
         );
     }
 
-    #[bench]
-    fn ms1b_1(b: &mut Bencher) {
-        b.iter(|| ms1b(1 << 50))
-    }
+    #[test]
+    fn mailbox() {
+        let mut bitboards = BitBoards::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR");
+        assert!(bitboards.is_consistent());
+        assert_eq!(bitboards.mailbox[1], Piece::Knight | Color::Black);
+        assert_eq!(bitboards.mailbox[60], Piece::King | Color::White);
+        assert_eq!(bitboards.mailbox[36], EMPTY);
+
+        assert_eq!(bitboards.remove(62), Some((Piece::Knight, Color::White)));
+        assert_eq!(bitboards.remove(62), None);
+        bitboards.put(45, Piece::Knight, Color::White);
+        assert_eq!(bitboards.piece_at(45), Some((Piece::Knight, Color::White)));
+        assert!(bitboards.is_consistent());
 
-    // #[bench]
-    // fn ms1b_2(b: &mut Bencher) {
-    //     b.iter(|| ms1br(1 << 50))
-    // }
+        bitboards.knights ^= 1 << 45;
+        assert!(!bitboards.is_consistent());
+    }
 }
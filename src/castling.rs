@@ -0,0 +1,177 @@
+use crate::rays::*;
+
+/// Squares involved in castling with the rook on `rook`, for a king on `king`. Both pieces
+/// must sit on the same back rank; the rook may stand on any file (Chess960).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CastlingPath {
+    pub king: usize,
+    pub rook: usize,
+    pub king_to: usize,
+    pub rook_to: usize,
+    /// Squares that must be unoccupied, ignoring the castling king and rook.
+    pub empty: u64,
+    /// Squares the king walks through (destination included) that must not be attacked.
+    pub safe: u64,
+}
+
+impl CastlingPath {
+    pub const fn new(king: usize, rook: usize) -> Self {
+        let rank = king & !7;
+        let (king_to, rook_to) = if rook > king {
+            (rank + 6, rank + 5)
+        } else {
+            (rank + 2, rank + 3)
+        };
+        let king_walk = between_inclusive(king, king_to);
+        let rook_walk = between_inclusive(rook, rook_to);
+        Self {
+            king,
+            rook,
+            king_to,
+            rook_to,
+            empty: (king_walk | rook_walk) & !(1 << king | 1 << rook),
+            safe: king_walk & !(1 << king),
+        }
+    }
+
+    pub const fn is_kingside(&self) -> bool {
+        self.rook > self.king
+    }
+
+    /// Square the king is dropped on to castle. Castling is encoded as the king moving two
+    /// squares when that is unambiguous (every standard setup), and as king-takes-rook otherwise.
+    pub const fn target(&self) -> usize {
+        if self.king.abs_diff(self.king_to) == 2 {
+            self.king_to
+        } else {
+            self.rook
+        }
+    }
+}
+
+/// Squares from `a` to `b` along a rank, both ends included.
+const fn between_inclusive(a: usize, b: usize) -> u64 {
+    if a == b {
+        1 << a
+    } else {
+        RANKFILES_INTERSECT[a][b] | 1 << a | 1 << b
+    }
+}
+
+/// Back rank of the Chess960 start position `n` (Scharnagl numbering, 0..960),
+/// as uppercase piece letters from the a-file to the h-file. Position 518 is the standard setup.
+pub fn chess960_back_rank(n: usize) -> [char; 8] {
+    assert!(n < 960, "Chess960 positions are numbered 0..960, got {n}");
+    let mut rank = [' '; 8];
+    let (n, light) = (n / 4, n % 4);
+    rank[2 * light + 1] = 'B';
+    let (n, dark) = (n / 4, n % 4);
+    rank[2 * dark] = 'B';
+    let (n, queen) = (n / 6, n % 6);
+    place_nth_empty(&mut rank, queen, 'Q');
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+    let (first, second) = KNIGHTS[n];
+    // place the second knight first so the first one's index is not shifted
+    place_nth_empty(&mut rank, second, 'N');
+    place_nth_empty(&mut rank, first, 'N');
+    place_nth_empty(&mut rank, 0, 'R');
+    place_nth_empty(&mut rank, 0, 'K');
+    place_nth_empty(&mut rank, 0, 'R');
+    rank
+}
+
+fn place_nth_empty(rank: &mut [char; 8], n: usize, piece: char) {
+    let file = (0..8).filter(|&f| rank[f] == ' ').nth(n).unwrap();
+    rank[file] = piece;
+}
+
+/// FEN of the Chess960 start position `n`, with Shredder-FEN castling rights.
+pub fn chess960_fen(n: usize) -> String {
+    let rank = chess960_back_rank(n);
+    let white: String = rank.iter().collect();
+    let black = white.to_ascii_lowercase();
+    let rooks: String = (0..8)
+        .rev()
+        .filter(|&f| rank[f] == 'R')
+        .map(|f| (b'A' + f as u8) as char)
+        .collect();
+    format!(
+        "{black}/pppppppp/8/8/8/8/PPPPPPPP/{white} w {rooks}{} - 0 1",
+        rooks.to_ascii_lowercase()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_paths() {
+        // e1-h1 and e1-a1
+        let ks = CastlingPath::new(60, 63);
+        assert_eq!((ks.king_to, ks.rook_to), (62, 61));
+        assert_eq!(ks.empty, 1 << 61 | 1 << 62);
+        assert_eq!(ks.safe, 1 << 61 | 1 << 62);
+        assert_eq!(ks.target(), 62);
+        let qs = CastlingPath::new(60, 56);
+        assert_eq!((qs.king_to, qs.rook_to), (58, 59));
+        assert_eq!(qs.empty, 1 << 57 | 1 << 58 | 1 << 59);
+        assert_eq!(qs.safe, 1 << 58 | 1 << 59);
+        assert_eq!(qs.target(), 58);
+    }
+
+    #[test]
+    fn chess960_paths() {
+        // king g1 with rook h1: the king stays put, only f1 has to be free
+        let ks = CastlingPath::new(62, 63);
+        assert_eq!(ks.empty, 1 << 61);
+        assert_eq!(ks.safe, 0);
+        assert_eq!(ks.target(), 63);
+        // king b8 with rook a8: king to c8, rook to d8
+        let qs = CastlingPath::new(1, 0);
+        assert_eq!(qs.empty, 1 << 2 | 1 << 3);
+        assert_eq!(qs.safe, 1 << 2);
+        assert_eq!(qs.target(), 0);
+    }
+
+    #[test]
+    fn chess960_start_positions() {
+        assert_eq!(
+            chess960_back_rank(518).iter().collect::<String>(),
+            "RNBQKBNR"
+        );
+        assert_eq!(chess960_back_rank(0).iter().collect::<String>(), "BBQNNRKR");
+        assert_eq!(
+            chess960_back_rank(959).iter().collect::<String>(),
+            "RKRNNQBB"
+        );
+        assert_eq!(
+            chess960_fen(518),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+
+        let mut seen = std::collections::HashSet::new();
+        for n in 0..960 {
+            let rank = chess960_back_rank(n);
+            let files = |p| (0..8).filter(move |&f| rank[f] == p);
+            let bishops: Vec<_> = files('B').collect();
+            assert_eq!(bishops.len(), 2);
+            assert_ne!(bishops[0] % 2, bishops[1] % 2);
+            let rooks: Vec<_> = files('R').collect();
+            let king = files('K').next().unwrap();
+            assert!(rooks[0] < king && king < rooks[1]);
+            assert!(seen.insert(rank));
+        }
+    }
+}
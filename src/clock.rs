@@ -0,0 +1,336 @@
+//! Chess clocks: time controls of one or more stages, with Fischer increments, Bronstein
+//! delay or US (simple) delay. Time comes from a `TimeSource`, so tests can move it by hand.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::letterbox::Color;
+
+/// Where clocks read the time. Only differences between readings matter.
+pub trait TimeSource: Send + Sync {
+    fn now(&self) -> Duration;
+}
+
+/// The time of the computer.
+pub struct WallTime {
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    start: std::time::Instant,
+}
+
+impl Default for WallTime {
+    fn default() -> Self {
+        WallTime {
+            #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for WallTime {
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // `Instant` is not available in the browser
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+    }
+}
+
+/// Time that only moves when told to. Clones share the same time.
+#[derive(Clone, Debug, Default)]
+pub struct ManualTime(Arc<AtomicU64>);
+
+impl ManualTime {
+    pub fn advance(&self, by: Duration) {
+        self.0.fetch_add(by.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// What the `+` of a stage adds to a move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The increment is added after every move.
+    Fischer,
+    /// After every move, the time it took is given back, up to the delay.
+    Bronstein,
+    /// The clock only starts counting down once the delay has passed (US delay).
+    Delay,
+}
+
+impl Mode {
+    pub fn parse(name: &str) -> Option<Mode> {
+        match name {
+            "fischer" => Some(Mode::Fischer),
+            "bronstein" => Some(Mode::Bronstein),
+            "delay" | "us" => Some(Mode::Delay),
+            _ => None,
+        }
+    }
+}
+
+/// One period of a time control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stage {
+    /// Moves to play in this stage, `None` for the rest of the game.
+    pub moves: Option<u32>,
+    /// Added to the clock when the stage begins.
+    pub time: Duration,
+    /// Increment or delay, depending on the `Mode`.
+    pub bonus: Duration,
+}
+
+/// Stages in the PGN `TimeControl` notation, in seconds and separated by colons:
+/// `40/5400+30:1800+30` is 90 minutes for 40 moves then 30 for the rest, plus 30 seconds a
+/// move. A last stage with a move count repeats.
+pub fn parse_time_control(spec: &str) -> Option<Vec<Stage>> {
+    let seconds = |s: &str| s.parse::<f64>().ok().map(Duration::from_secs_f64);
+    spec.split(':')
+        .map(|stage| {
+            let (moves, rest) = match stage.split_once('/') {
+                Some((moves, rest)) => (Some(moves.parse().ok().filter(|&n| n > 0)?), rest),
+                None => (None, stage),
+            };
+            let (time, bonus) = rest.split_once('+').unwrap_or((rest, "0"));
+            Some(Stage {
+                moves,
+                time: seconds(time)?,
+                bonus: seconds(bonus)?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Side {
+    remaining: Duration,
+    stage: usize,
+    /// Moves played in the current stage.
+    moves: u32,
+}
+
+/// A clock for both players. Only one side's time runs at once.
+#[derive(Clone)]
+pub struct Clock {
+    stages: Vec<Stage>,
+    mode: Mode,
+    sides: [Side; 2],
+    /// The side whose time runs, and when its move started.
+    running: Option<(Color, Duration)>,
+    source: Arc<dyn TimeSource>,
+}
+
+impl Clock {
+    /// A stopped clock. Panics without stages.
+    pub fn new(stages: Vec<Stage>, mode: Mode, source: Arc<dyn TimeSource>) -> Self {
+        let side = Side {
+            remaining: stages[0].time,
+            ..Default::default()
+        };
+        Clock {
+            stages,
+            mode,
+            sides: [side; 2],
+            running: None,
+            source,
+        }
+    }
+
+    /// Starts the time of `side`, stopping the other's without ending its move.
+    pub fn start(&mut self, side: Color) {
+        self.stop();
+        self.running = Some((side, self.source.now()));
+    }
+
+    pub fn stop(&mut self) {
+        for color in [Color::White, Color::Black] {
+            self.sides[color.index()].remaining = self.remaining(color);
+        }
+        self.running = None;
+    }
+
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(side, _)| side)
+    }
+
+    /// Time `side` has left, counting the move it is thinking on.
+    pub fn remaining(&self, side: Color) -> Duration {
+        let clock = &self.sides[side.index()];
+        match self.running {
+            Some((running, start)) if running == side => {
+                let spent = self.source.now().saturating_sub(start);
+                clock.remaining.saturating_sub(self.charged(side, spent))
+            }
+            _ => clock.remaining,
+        }
+    }
+
+    /// Whether `side` has run out of time.
+    pub fn flagged(&self, side: Color) -> bool {
+        self.remaining(side).is_zero()
+    }
+
+    fn stage(&self, side: Color) -> &Stage {
+        &self.stages[self.sides[side.index()].stage]
+    }
+
+    /// Time taken off the clock for a move that took `spent`.
+    fn charged(&self, side: Color, spent: Duration) -> Duration {
+        match self.mode {
+            Mode::Delay => spent.saturating_sub(self.stage(side).bonus),
+            Mode::Fischer | Mode::Bronstein => spent,
+        }
+    }
+
+    /// Ends the move of the running side and starts the other's time. Nothing happens if the
+    /// clock is stopped or the running side has flagged.
+    pub fn press(&mut self) {
+        let Some((side, start)) = self.running else {
+            return;
+        };
+        if self.flagged(side) {
+            return;
+        }
+        let spent = self.source.now().saturating_sub(start);
+        let remaining = self.remaining(side);
+        let stage = *self.stage(side);
+        let last = self.stages.len() - 1;
+        let clock = &mut self.sides[side.index()];
+        clock.remaining = remaining
+            + match self.mode {
+                Mode::Fischer => stage.bonus,
+                Mode::Bronstein => spent.min(stage.bonus),
+                Mode::Delay => Duration::ZERO,
+            };
+        clock.moves += 1;
+        if Some(clock.moves) == stage.moves {
+            clock.stage = (clock.stage + 1).min(last);
+            clock.moves = 0;
+            clock.remaining += self.stages[clock.stage].time;
+        }
+        self.running = Some((!side, self.source.now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    fn manual(spec: &str, mode: Mode) -> (Clock, ManualTime) {
+        let time = ManualTime::default();
+        let clock = Clock::new(
+            parse_time_control(spec).unwrap(),
+            mode,
+            Arc::new(time.clone()),
+        );
+        (clock, time)
+    }
+
+    #[test]
+    fn time_controls() {
+        assert_eq!(
+            parse_time_control("40/5400+30:1800+30").unwrap(),
+            [
+                Stage {
+                    moves: Some(40),
+                    time: seconds(5400),
+                    bonus: seconds(30),
+                },
+                Stage {
+                    moves: None,
+                    time: seconds(1800),
+                    bonus: seconds(30),
+                },
+            ]
+        );
+        assert_eq!(
+            parse_time_control("0.5").unwrap()[0].time,
+            Duration::from_millis(500)
+        );
+        assert_eq!(parse_time_control("40/"), None);
+        assert_eq!(parse_time_control("0/60"), None);
+        assert_eq!(parse_time_control("60+x"), None);
+        assert_eq!(Mode::parse("us"), Some(Mode::Delay));
+    }
+
+    #[test]
+    fn fischer() {
+        let (mut clock, time) = manual("60+2", Mode::Fischer);
+        clock.start(Color::White);
+        time.advance(seconds(5));
+        assert_eq!(clock.remaining(Color::White), seconds(55));
+        clock.press();
+        assert_eq!(clock.running(), Some(Color::Black));
+        assert_eq!(clock.remaining(Color::White), seconds(57));
+        time.advance(seconds(1));
+        clock.press();
+        assert_eq!(clock.remaining(Color::Black), seconds(61));
+        time.advance(seconds(60));
+        assert!(clock.flagged(Color::White));
+        clock.press();
+        assert_eq!(clock.running(), Some(Color::White));
+        clock.stop();
+        assert_eq!(clock.running(), None);
+        assert!(clock.flagged(Color::White));
+    }
+
+    #[test]
+    fn delays() {
+        // Bronstein gives back what the move took, up to the delay
+        let (mut clock, time) = manual("60+3", Mode::Bronstein);
+        clock.start(Color::White);
+        time.advance(seconds(2));
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), seconds(60));
+        time.advance(seconds(5));
+        clock.press();
+        assert_eq!(clock.remaining(Color::Black), seconds(58));
+
+        // US delay only counts what goes past it, and never adds
+        let (mut clock, time) = manual("60+3", Mode::Delay);
+        clock.start(Color::White);
+        time.advance(seconds(2));
+        assert_eq!(clock.remaining(Color::White), seconds(60));
+        clock.press();
+        time.advance(seconds(5));
+        assert_eq!(clock.remaining(Color::Black), seconds(58));
+        clock.press();
+        assert_eq!(clock.remaining(Color::Black), seconds(58));
+    }
+
+    #[test]
+    fn stages() {
+        let (mut clock, time) = manual("2/60:30+5", Mode::Fischer);
+        clock.start(Color::White);
+        for _ in 0..4 {
+            time.advance(seconds(10));
+            clock.press();
+        }
+        // both made their two moves: the second stage's time is added
+        assert_eq!(clock.remaining(Color::White), seconds(70));
+        assert_eq!(clock.remaining(Color::Black), seconds(70));
+        time.advance(seconds(10));
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), seconds(65));
+
+        // a last stage with a move count comes back
+        let (mut clock, time) = manual("1/10", Mode::Fischer);
+        clock.start(Color::White);
+        time.advance(seconds(4));
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), seconds(16));
+    }
+}
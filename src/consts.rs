@@ -22,6 +22,8 @@ pub const A_FILE: u64 = 0x0101010101010101;
 pub const G_FILE: u64 = H_FILE >> 1;
 pub const B_FILE: u64 = A_FILE << 1;
 pub const ONES: u64 = u64::MAX;
+/// a8, c8, ..., b7, d7, ...: the squares of a8's colour.
+pub const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
 
 pub const NOT_H_FILE: u64 = !H_FILE;
 pub const NOT_A_FILE: u64 = !A_FILE;
@@ -53,39 +55,25 @@ const_moves!(BLACK_PAWN_ATTACKS <- pawn_attacks::<SW, SE>;);
 const_moves!(BLACK_PAWN_ADVANCES <- pawn_advances::<S, ONES>; ONES);
 
 pub struct Consts {
+    pub first_rank: u64,
     pub eighth_rank: u64,
+    pub second_rank: u64,
     pub third_rank: u64,
-    pub ks_castle: u64,
-    pub ks_castle_k: u64,
-    pub ks_rook: u64,
-    pub qs_castle: u64,
-    pub qs_castle_k: u64,
-    pub qs_rook: u64,
     pub direction: i8,
 }
 impl Consts {
     pub const WHITE: Consts = Consts {
-        // first_rank: 0xFF00000000000000,
+        first_rank: 0xFF00000000000000,
+        second_rank: 0x00FF000000000000,
         third_rank: 0x0000FF0000000000,
         eighth_rank: 0x00000000000000FF,
-        ks_castle: 0x6000000000000000,
-        ks_castle_k: 0x4000000000000000,
-        ks_rook: 0x8000000000000000,
-        qs_castle: 0xE00000000000000,
-        qs_castle_k: 0x400000000000000,
-        qs_rook: 0x100000000000000,
         direction: 1,
     };
     pub const BLACK: Consts = Consts {
-        // first_rank: 0x00000000000000FF,
+        first_rank: 0x00000000000000FF,
+        second_rank: 0x000000000000FF00,
         third_rank: 0x0000000000FF0000,
         eighth_rank: 0xFF00000000000000,
-        ks_castle: 0x60,
-        ks_castle_k: 0x40,
-        ks_rook: 0x80,
-        qs_castle: 0xE,
-        qs_castle_k: 0x4,
-        qs_rook: 1,
         direction: -1,
     };
 }
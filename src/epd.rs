@@ -0,0 +1,123 @@
+//! Extended Position Description records, as used by test suites such as WAC or STS.
+
+use crate::gamestate::{Game, Move};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Epd {
+    /// Full FEN of the position, move counters taken from `hmvc`/`fmvn` when present.
+    pub fen: String,
+    /// `bm`: moves in SAN, any of which solves the position.
+    pub best_moves: Vec<String>,
+    /// `am`: moves in SAN that fail the position.
+    pub avoid_moves: Vec<String>,
+    pub id: Option<String>,
+    /// `c0`: the primary comment.
+    pub comment: Option<String>,
+}
+
+/// Splits `text` on `separator`, except inside double quotes.
+fn split_unquoted(text: &str, separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && separator(c) {
+            parts.push(&text[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+impl Epd {
+    /// Parses one EPD line: four FEN fields, then `;`-terminated operations.
+    /// Opcodes other than `bm`, `am`, `id`, `c0`, `hmvc` and `fmvn` are ignored.
+    pub fn parse(line: &str) -> Option<Epd> {
+        let mut rest = line.trim();
+        let mut fields = vec![];
+        for _ in 0..4 {
+            let (field, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if field.is_empty() {
+                return None;
+            }
+            fields.push(field);
+            rest = tail.trim_start();
+        }
+
+        let mut epd = Epd::default();
+        let (mut halfmove, mut fullmove) = ("0", "1");
+        for operation in split_unquoted(rest, |c| c == ';') {
+            let mut operands = split_unquoted(operation, char::is_whitespace).into_iter();
+            let Some(opcode) = operands.next() else {
+                continue;
+            };
+            let string = || {
+                operation
+                    .trim()
+                    .get(opcode.len()..)
+                    .map(|s| s.trim().trim_matches('"').to_string())
+            };
+            match opcode {
+                "bm" => epd.best_moves = operands.map(str::to_string).collect(),
+                "am" => epd.avoid_moves = operands.map(str::to_string).collect(),
+                "id" => epd.id = string(),
+                "c0" => epd.comment = string(),
+                "hmvc" => halfmove = operands.next()?,
+                "fmvn" => fullmove = operands.next()?,
+                _ => {}
+            }
+        }
+        epd.fen = format!("{} {halfmove} {fullmove}", fields.join(" "));
+        Some(epd)
+    }
+
+    pub fn game(&self) -> Game {
+        Game::from_fen(&self.fen)
+    }
+
+    /// Whether `played` is one of the best moves, if any are given, and none of the moves to
+    /// avoid.
+    pub fn solved_by(&self, game: &Game, played: Move) -> bool {
+        let matches =
+            |moves: &[String]| moves.iter().any(|san| game.parse_san(san) == Some(played));
+        (self.best_moves.is_empty() || matches(&self.best_moves)) && !matches(&self.avoid_moves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let epd = Epd::parse(
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#,
+        )
+        .unwrap();
+        assert_eq!(
+            epd.fen,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(epd.best_moves, ["Qg6"]);
+        assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+        let game = epd.game();
+        assert!(epd.solved_by(&game, (46, 22, None)));
+        assert!(!epd.solved_by(&game, (46, 38, None)));
+
+        let epd = Epd::parse(
+            r#"1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - - am Qd1+ Kc8; c0 "a; quoted comment"; id "BK.01"; hmvc 3; fmvn 20;"#,
+        )
+        .unwrap();
+        assert!(epd.fen.ends_with(" b - - 3 20"));
+        assert_eq!(epd.avoid_moves, ["Qd1+", "Kc8"]);
+        assert_eq!(epd.comment.as_deref(), Some("a; quoted comment"));
+        let game = epd.game();
+        assert!(!epd.solved_by(&game, game.parse_san("Qd1+").unwrap()));
+        assert!(epd.solved_by(&game, game.parse_san("Qd2+").unwrap()));
+
+        assert_eq!(Epd::parse("8/8/8 w"), None);
+    }
+}
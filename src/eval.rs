@@ -0,0 +1,133 @@
+//! Static evaluation in centipawns, from the point of view of the side to move.
+
+use serde::{Deserialize, Serialize};
+
+use crate::bitboards::*;
+use crate::gamestate::Game;
+use crate::letterbox::{Color, Piece};
+
+/// Material value of each piece, indexed by `Piece`. Kings are priceless and count zero.
+pub const PIECE_VALUES: [i32; 7] = [0, 100, 320, 330, 500, 900, 0];
+
+/// Steps from the edge of the board, 0 on the rim to 6 on the four center squares.
+fn centrality(square: usize) -> i32 {
+    let (file, rank) = (square % 8, square / 8);
+    (file.min(7 - file) + rank.min(7 - rank)) as i32
+}
+
+/// Number of tunable weights in `Params`.
+pub const PARAMS: usize = 8;
+
+/// Weights of the evaluation terms, which `evaluate_with` sums linearly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Params {
+    /// Pawn, knight, bishop, rook and queen.
+    pub material: [i32; 5],
+    /// Per step of a knight away from the rim.
+    pub knight_centrality: i32,
+    /// Per step of a bishop or queen away from the rim.
+    pub bishop_centrality: i32,
+    /// Per rank a pawn has advanced.
+    pub pawn_advance: i32,
+}
+
+pub const DEFAULT_PARAMS: Params = Params {
+    material: [100, 320, 330, 500, 900],
+    knight_centrality: 4,
+    bishop_centrality: 2,
+    pawn_advance: 5,
+};
+
+impl Params {
+    pub fn weights(&self) -> [i32; PARAMS] {
+        let [p, n, b, r, q] = self.material;
+        [
+            p,
+            n,
+            b,
+            r,
+            q,
+            self.knight_centrality,
+            self.bishop_centrality,
+            self.pawn_advance,
+        ]
+    }
+
+    pub fn from_weights(weights: [i32; PARAMS]) -> Self {
+        let [p, n, b, r, q, knight_centrality, bishop_centrality, pawn_advance] = weights;
+        Params {
+            material: [p, n, b, r, q],
+            knight_centrality,
+            bishop_centrality,
+            pawn_advance,
+        }
+    }
+}
+
+/// How much each weight of `Params` counts in the position, white's terms minus black's.
+pub fn terms(game: &Game) -> [i32; PARAMS] {
+    let bitboards = &game.bitboards;
+    let mut terms = [0; PARAMS];
+    for (color, sign) in [(bitboards.whites, 1), (bitboards.blacks, -1)] {
+        apply!(color, square -> {
+            let (piece, _) = bitboards.piece_at(square).unwrap();
+            if piece != Piece::King {
+                terms[piece as usize - 1] += sign;
+            }
+            match piece {
+                Piece::Knight => terms[5] += sign * centrality(square),
+                Piece::Bishop | Piece::Queen => terms[6] += sign * centrality(square),
+                Piece::Pawn => {
+                    let rank = square / 8;
+                    terms[7] += if sign > 0 { 6 - rank as i32 } else { 1 - rank as i32 };
+                }
+                _ => {}
+            }
+        });
+    }
+    terms
+}
+
+/// Material, centralized minor pieces and advanced pawns.
+pub fn evaluate(game: &Game) -> i32 {
+    evaluate_with(game, &DEFAULT_PARAMS)
+}
+
+/// `evaluate` with other weights.
+pub fn evaluate_with(game: &Game, params: &Params) -> i32 {
+    let weights = params.weights();
+    let score: i32 = terms(game).iter().zip(weights).map(|(t, w)| t * w).sum();
+    if game.turn == Color::White {
+        score
+    } else {
+        -score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symmetric() {
+        let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(evaluate(&game), 0);
+        let white = Game::from_fen("4k3/8/8/8/4P3/2N5/8/4K3 w - - 0 1");
+        let black = Game::from_fen("4k3/8/2n5/4p3/8/8/8/4K3 b - - 0 1");
+        assert_eq!(evaluate(&white), evaluate(&black));
+        assert!(evaluate(&white) > 400);
+    }
+
+    #[test]
+    fn params() {
+        assert_eq!(DEFAULT_PARAMS.material, PIECE_VALUES[1..6]);
+        assert_eq!(
+            Params::from_weights(DEFAULT_PARAMS.weights()),
+            DEFAULT_PARAMS
+        );
+        let game = Game::from_fen("4k3/8/8/8/4P3/2N5/8/4K3 w - - 0 1");
+        assert_eq!(terms(&game), [1, 1, 0, 0, 0, 4, 0, 2]);
+        let pawns = Params::from_weights([1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(evaluate_with(&game, &pawns), 1);
+    }
+}
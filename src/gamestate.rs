@@ -1,81 +1,569 @@
+use std::sync::Arc;
+
+#[cfg(feature = "wasm")]
 use js_sys::Int32Array;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 use crate::bitboards::*;
+use crate::castling::*;
+use crate::clock::*;
 use crate::consts::*;
+use crate::letterbox::{Color, Piece};
 use crate::moves::*;
+use crate::notation::*;
+use crate::variant::*;
 
-#[derive(Serialize, Deserialize)]
-struct MoveResponse {
-    updates: Vec<(usize, i32)>,
+/// What a move did to the board, for the UI to replay it.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveResult {
+    /// Square the piece left, nothing for drops.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(readonly))]
+    pub from: Option<usize>,
+    /// Square the piece landed on: the king's destination when castling.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(readonly))]
+    pub to: usize,
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub piece: Piece,
+    /// Captured piece and its square, which differs from `to` en passant.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub captured: Option<(usize, Piece)>,
+    /// Castling rook, from and to.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub rook: Option<(usize, usize)>,
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub promotion: Option<Piece>,
+    /// Squares emptied by the variant after the move, such as Atomic explosions.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub removed: Vec<usize>,
+    #[cfg_attr(feature = "wasm", wasm_bindgen(readonly))]
+    pub check: bool,
+    #[cfg_attr(feature = "wasm", wasm_bindgen(readonly))]
+    pub mate: bool,
+    /// Standard algebraic notation, `Nbd7`, `exd8=Q+`, `O-O#` or `N@f3` for drops.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub san: String,
 }
 
-struct Move {
-    from_sq: usize,
-    from_bb: u64,
-    to_sq: usize,
-    to_bb: u64,
-    promotion: i32,
-}
-impl Move {
-    pub fn new(from_sq: usize, to_sq: usize) -> Self {
-        Move {
-            from_sq,
-            from_bb: 1 << from_sq,
-            to_sq,
-            to_bb: 1 << to_sq,
-            promotion: 0,
-        }
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl MoveResult {
+    /// The moved or dropped piece.
+    #[wasm_bindgen(getter)]
+    pub fn piece(&self) -> Piece {
+        self.piece
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn captured(&self) -> Option<Piece> {
+        self.captured.map(|(_, piece)| piece)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn captured_square(&self) -> Option<usize> {
+        self.captured.map(|(square, _)| square)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rook_from(&self) -> Option<usize> {
+        self.rook.map(|(from, _)| from)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rook_to(&self) -> Option<usize> {
+        self.rook.map(|(_, to)| to)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn promotion(&self) -> Option<Piece> {
+        self.promotion
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn removed(&self) -> Int32Array {
+        let removed: Vec<i32> = self.removed.iter().map(|&square| square as i32).collect();
+        Int32Array::from(&removed[..])
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn san(&self) -> String {
+        self.san.clone()
     }
 }
 
 pub struct State {}
 
-#[wasm_bindgen]
+/// A move as `(from, to, promotion)`. Castling is the king moving to `CastlingPath::target`.
+pub type Move = (usize, usize, Option<Piece>);
+
+pub type MoveList = Vec<Move>;
+
+/// A move, or a drop of a piece in hand onto a square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Move(Move),
+    Drop(Piece, usize),
+}
+
+/// The board and the FEN fields around it, as a plain value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub board: BitBoards,
+    pub turn: Color,
+    /// Squares of the rooks that may still castle.
+    pub castling: u64,
+    pub enpassant: Option<usize>,
+    pub halfmove: u32,
+    pub fullmove: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone)]
 pub struct Game {
-    bitboards: BitBoards,
-    turn: bool,
-    castling: u64,
-    enpassant: Option<usize>,
-    halfmove: u32,
-    fullmove: u32,
-    legal_moves: [u64; 64],
-    winner: Option<bool>,
-    stalemate: bool,
+    pub(crate) bitboards: BitBoards,
+    pub(crate) turn: Color,
+    pub(crate) castling: u64,
+    pub(crate) chess960: bool,
+    pub(crate) enpassant: Option<usize>,
+    pub(crate) halfmove: u32,
+    pub(crate) fullmove: u32,
+    pub(crate) legal_moves: [u64; 64],
+    pub(crate) variant: &'static dyn Variant,
+    /// Checks given so far, indexed by color (Three-check).
+    pub(crate) checks: [u8; 2],
+    /// Pieces in hand, indexed by color then in `Piece::POCKET` order (Crazyhouse).
+    pub(crate) pockets: [[u8; 5]; 2],
+    /// Pieces promoted from pawns, which go back to a pocket as pawns (Crazyhouse).
+    pub(crate) promoted: u64,
+    /// Squares the side to move may drop pieces on, pawns excepted on the back ranks.
+    pub(crate) drops: u64,
+    pub(crate) outcome: Option<Outcome>,
+    pub(crate) clock: Option<Clock>,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Game {
-    #[wasm_bindgen(constructor)]
+    /// `variant` is one of the names in `variant::VARIANTS`, standard chess if omitted. Fails
+    /// with a message the UI can show on an unknown variant or a malformed FEN.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(fen: &str, variant: Option<String>) -> Result<Game, String> {
+        let variant = match variant {
+            Some(name) => {
+                variant_by_name(&name).ok_or_else(|| format!("Unknown variant: {name}"))?
+            }
+            None => &Standard,
+        };
+        Game::try_with_variant(fen, variant).ok_or_else(|| format!("Invalid FEN: {fen}"))
+    }
+
     pub fn from_fen(fen: &str) -> Self {
+        Game::with_variant(fen, &Standard)
+    }
+
+    /// Chess960 start position `n` (0..960, 518 is the standard setup).
+    pub fn from_chess960(n: usize) -> Self {
+        let mut game = Game::from_fen(&chess960_fen(n));
+        game.chess960 = true;
+        game
+    }
+
+    /// Mirrors the UCI `UCI_Chess960` option: castling moves are written as king-takes-rook.
+    /// Castling setups that only exist in Chess960 always use it.
+    pub fn set_chess960(&mut self, enabled: bool) {
+        self.chess960 = enabled || !is_standard_castling(self.castling, &self.bitboards);
+    }
+
+    pub fn turn(&mut self) {
+        if self.turn == Color::Black {
+            self.fullmove += 1;
+        }
+        self.turn = !self.turn
+    }
+
+    pub fn calc_legal_moves(&mut self) {
+        debug_assert!(
+            self.bitboards.is_consistent(),
+            "mailbox out of step with bitboards"
+        );
+        self.legal_moves = self.variant.legal_moves(self);
+        self.drops = self.variant.legal_drops(self);
+        self.outcome = self.variant.outcome(self);
+        if self.outcome.is_some() {
+            self.legal_moves = [0; 64];
+            self.drops = 0;
+        }
+    }
+
+    pub fn variant(&self) -> String {
+        self.variant.name().to_string()
+    }
+
+    /// Whether the game is over, by checkmate, stalemate or a variant rule.
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.turn
+    }
+
+    /// The side that won, nothing for draws and unfinished games.
+    pub fn winner(&self) -> Option<Color> {
+        match self.outcome {
+            Some(Outcome::Winner(color)) => Some(color),
+            _ => None,
+        }
+    }
+
+    /// Whether the king of the side to move is attacked.
+    pub fn is_check(&self) -> bool {
+        let friends = self.bitboards.color(self.turn);
+        let foes = self.bitboards.color(!self.turn);
+        let king = friends & self.bitboards.kings;
+        king != 0 && attackers_to(bsf(king) as usize, foes, &self.bitboards, friends | foes) != 0
+    }
+
+    pub fn send_board(&self) -> String {
+        serde_json::to_string(&self.bitboards).expect("oh shit")
+    }
+
+    /// Plays `from -> to`, nothing if the move is illegal. Moves to the last rank need the
+    /// piece the pawn promotes to, and other moves none.
+    pub fn input_move(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<Piece>,
+    ) -> Option<MoveResult> {
+        if self.check_time() {
+            return None;
+        }
+        let result = self.make_move(from, to, promotion)?;
+        self.press_clock();
+        Some(result)
+    }
+
+    /// Drops a pocket piece on `to`, nothing if it may not.
+    pub fn input_drop(&mut self, piece: Piece, to: usize) -> Option<MoveResult> {
+        if self.check_time() {
+            return None;
+        }
+        let result = self.drop_piece(piece, to)?;
+        self.press_clock();
+        Some(result)
+    }
+
+    /// Puts clocks on the game, timed by the computer, and starts the side to move's.
+    /// `control` is in PGN `TimeControl` notation (`40/5400+30:1800+30`), `mode` one of
+    /// `fischer`, `bronstein` or `delay`. False if either does not parse.
+    pub fn set_time_control(&mut self, control: &str, mode: &str) -> bool {
+        let (Some(stages), Some(mode)) = (parse_time_control(control), Mode::parse(mode)) else {
+            return false;
+        };
+        self.set_clock(Clock::new(stages, mode, Arc::new(WallTime::default())));
+        true
+    }
+
+    /// Milliseconds `color` has left, nothing without clocks.
+    pub fn time_left(&self, color: Color) -> Option<f64> {
+        let clock = self.clock.as_ref()?;
+        Some(clock.remaining(color).as_secs_f64() * 1000.0)
+    }
+
+    /// Ends the game if the side to move has run out of time, and says whether it did. The
+    /// flag loses, unless the other side could not mate at all in standard chess.
+    pub fn check_time(&mut self) -> bool {
+        let Some(clock) = &mut self.clock else {
+            return false;
+        };
+        if self.outcome.is_some() || !clock.flagged(self.turn) {
+            return false;
+        }
+        clock.stop();
+        let drawn = self.variant.name() == "standard" && !self.has_mating_material(!self.turn);
+        self.outcome = Some(if drawn {
+            Outcome::Draw
+        } else {
+            Outcome::Winner(!self.turn)
+        });
+        self.legal_moves = [0; 64];
+        self.drops = 0;
+        true
+    }
+
+    /// Pocket contents, as JSON `[{"kind": "knight", "color": "white", "count": 2}, ...]`.
+    pub fn send_pockets(&self) -> String {
+        #[derive(Serialize)]
+        struct Reserve {
+            kind: Piece,
+            color: Color,
+            count: u8,
+        }
+        let mut reserves = vec![];
+        for color in [Color::White, Color::Black] {
+            for piece in Piece::POCKET {
+                let count = self.pockets[color.index()][piece as usize - 1];
+                if count > 0 {
+                    reserves.push(Reserve {
+                        kind: piece,
+                        color,
+                        count,
+                    });
+                }
+            }
+        }
+        serde_json::to_string(&reserves).unwrap()
+    }
+
+    pub fn to_fen(&self) -> String {
+        let pockets = self.variant.has_pockets();
+        let mut position = self
+            .bitboards
+            .to_fen(if pockets { self.promoted } else { 0 });
+        if pockets {
+            position.push('[');
+            for color in [Color::White, Color::Black] {
+                for piece in Piece::POCKET {
+                    let letter = if color == Color::White {
+                        piece.letter().to_ascii_uppercase()
+                    } else {
+                        piece.letter()
+                    };
+                    for _ in 0..self.pockets[color.index()][piece as usize - 1] {
+                        position.push(letter);
+                    }
+                }
+            }
+            position.push(']');
+        }
+        format!(
+            "{position} {} {} {} {} {}",
+            if self.turn == Color::White { "w" } else { "b" },
+            castling_fen(self.castling, &self.bitboards),
+            self.enpassant.map_or("-".to_string(), square_name),
+            self.halfmove,
+            self.fullmove
+        )
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl Game {
+    pub fn get_legal_moves(&mut self) -> Vec<Int32Array> {
+        self.legal_moves
+            .into_iter()
+            .map(|moves| {
+                let mut v = vec![];
+                apply!(moves, i -> v.push(i as i32));
+                Int32Array::from(&v[..])
+            })
+            .collect()
+    }
+
+    /// Squares the side to move may drop `piece` on.
+    pub fn get_legal_drops(&self, piece: Piece) -> Int32Array {
+        let squares = self.drop_squares(piece);
+        let mut v = vec![];
+        apply!(squares, i -> v.push(i as i32));
+        Int32Array::from(&v[..])
+    }
+}
+
+/// Castling rights from the FEN castling field, as the squares of the rooks that may castle.
+/// Accepts `KQkq` (X-FEN: the outermost rook on that side), Shredder-FEN rook files
+/// (`HAha`) and any mix of the two.
+fn parse_castling(field: &str, bitboards: &BitBoards) -> u64 {
+    field.chars().fold(0, |acc, c| {
+        let (color, consts) = if c.is_ascii_uppercase() {
+            (bitboards.whites, Consts::WHITE)
+        } else {
+            (bitboards.blacks, Consts::BLACK)
+        };
+        let rooks = color & bitboards.rooks & consts.first_rank;
+        let king = bsf(color & bitboards.kings & consts.first_rank | 1 << 63) as usize;
+        let kingside = rooks & ONES << king << 1;
+        let queenside = rooks & !(ONES << king);
+        acc | match c.to_ascii_lowercase() {
+            'k' if kingside != 0 => ms1b(kingside),
+            'q' if queenside != 0 => ls1b(queenside),
+            f @ 'a'..='h' => rooks & consts.first_rank & A_FILE << (f as u8 - b'a'),
+            _ => 0,
+        }
+    })
+}
+
+/// FEN castling field of `castling`: `KQkq` for the outermost rooks, Shredder-FEN rook files
+/// for the others.
+fn castling_fen(castling: u64, bitboards: &BitBoards) -> String {
+    let mut field = String::new();
+    for (color, consts) in [
+        (bitboards.whites, Consts::WHITE),
+        (bitboards.blacks, Consts::BLACK),
+    ] {
+        let rooks = color & bitboards.rooks & consts.first_rank;
+        let king = bsf(color & bitboards.kings & consts.first_rank | 1 << 63) as usize;
+        let kingside = rooks & ONES << king << 1;
+        let queenside = rooks & !(ONES << king);
+        let mut rights = castling & color & consts.first_rank;
+        while rights != 0 {
+            let rook = ms1b(rights);
+            rights ^= rook;
+            let letter = if kingside != 0 && rook == ms1b(kingside) {
+                'k'
+            } else if queenside != 0 && rook == ls1b(queenside) {
+                'q'
+            } else {
+                (b'a' + bsf(rook) as u8 % 8) as char
+            };
+            field.push(if color == bitboards.whites {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            });
+        }
+    }
+    if field.is_empty() {
+        field.push('-');
+    }
+    field
+}
+
+/// Splits the Crazyhouse pocket off a FEN piece placement, written either as `[Qp]` or as a
+/// ninth rank.
+fn split_pocket(position: &str) -> (&str, &str) {
+    if let Some((board, pocket)) = position.split_once('[') {
+        (board, pocket.trim_end_matches(']'))
+    } else if position.matches('/').count() == 8 {
+        position.rsplit_once('/').unwrap()
+    } else {
+        (position, "")
+    }
+}
+
+/// Squares of the pieces marked as promoted with a `~` in a FEN piece placement.
+fn parse_promoted(position: &str) -> u64 {
+    let mut promoted = 0;
+    let mut square = 0;
+    for c in position.chars() {
+        match c {
+            '/' => {}
+            '~' => promoted |= 1 << (square - 1),
+            c if c.is_ascii_digit() => square += c.to_digit(10).unwrap() as usize,
+            _ => square += 1,
+        }
+    }
+    promoted
+}
+
+/// Whether `fen` has every field `Game::with_variant` reads, each well formed: eight ranks of
+/// eight squares, the side to move, castling rights, the en passant square, the remaining
+/// checks of Three-check if given, and the move counters.
+fn is_valid_fen(fen: &str) -> bool {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let [position, turn, castling, enpassant, rest @ ..] = fields.as_slice() else {
+        return false;
+    };
+    let (board, pocket) = split_pocket(position);
+    let rank_ok = |rank: &str| {
+        let mut squares = 0;
+        let mut after_piece = false;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => squares += c.to_digit(10).unwrap(),
+                '~' if after_piece => {}
+                c if Piece::from_letter(c).is_some() => squares += 1,
+                _ => return false,
+            }
+            after_piece = c.is_ascii_alphabetic();
+        }
+        squares == 8
+    };
+    let counters = match rest {
+        [checks, counters @ ..] if checks.contains('+') => {
+            let (white, black) = checks.split_once('+').unwrap();
+            if white.parse::<u8>().is_err() || black.parse::<u8>().is_err() {
+                return false;
+            }
+            counters
+        }
+        counters => counters,
+    };
+    board.split('/').count() == 8
+        && board.split('/').all(rank_ok)
+        && pocket
+            .chars()
+            .all(|c| !matches!(Piece::from_letter(c), Some(Piece::King) | None))
+        && matches!(*turn, "w" | "b")
+        && (*castling == "-"
+            || castling
+                .chars()
+                .all(|c| matches!(c.to_ascii_lowercase(), 'k' | 'q' | 'a'..='h')))
+        && (*enpassant == "-" || matches!(enpassant.as_bytes(), [b'a'..=b'h', b'1'..=b'8']))
+        && matches!(counters, [halfmove, fullmove]
+            if halfmove.parse::<u32>().is_ok() && fullmove.parse::<u32>().is_ok())
+}
+
+fn parse_pocket(pocket: &str) -> [[u8; 5]; 2] {
+    let mut pockets = [[0; 5]; 2];
+    for c in pocket.chars() {
+        match Piece::from_letter(c) {
+            Some(Piece::King) | None => panic!("Unexpected piece in pocket: {c}"),
+            Some(piece) => pockets[c.is_ascii_uppercase() as usize][piece as usize - 1] += 1,
+        }
+    }
+    pockets
+}
+
+/// Whether every castling right in `castling` belongs to a king on the e-file and a rook in
+/// its corner, so castling can be written as a two-square king move.
+fn is_standard_castling(castling: u64, bitboards: &BitBoards) -> bool {
+    let corners = 1 << 0 | 1 << 7 | 1 << 56 | 1 << 63;
+    let kings = bitboards.kings & (1 << 4 | 1 << 60);
+    let mut standard = castling & !corners == 0;
+    apply!(castling, r -> {
+        standard &= kings & (1 << (r & !7 | 4)) != 0;
+    });
+    standard
+}
+
+impl Game {
+    /// Sets up `fen` under the rules of `variant`.
+    pub fn with_variant(fen: &str, variant: &'static dyn Variant) -> Self {
         let mut fields = fen.split_whitespace();
 
-        let position = fields.next().unwrap();
+        let (position, pocket) = split_pocket(fields.next().unwrap());
         let bitboards = BitBoards::from_fen(position);
+        let promoted = parse_promoted(position);
+        let pockets = parse_pocket(pocket);
         let turn = match fields.next().unwrap() {
-            "w" => true,
-            "b" => false,
+            "w" => Color::White,
+            "b" => Color::Black,
             c => panic!("Unexpected color: {c}"),
         };
-        let castling = fields.next().unwrap().chars().fold(0b0000, |acc, c| {
-            acc | match c {
-                'K' => Consts::WHITE.ks_castle,
-                'Q' => Consts::WHITE.qs_castle,
-                'k' => Consts::BLACK.ks_castle,
-                'q' => Consts::BLACK.qs_castle,
-                _ => 0,
-            }
-        });
+        let castling = parse_castling(fields.next().unwrap(), &bitboards);
         let mut enp = fields.next().unwrap().chars();
         let file = enp.next().and_then(|f| "abcdefgh".find(f));
         let rank = enp.next().map(|r| r.to_digit(10).unwrap() - 1);
         let enpassant = if let (Some(f), Some(r)) = (file, rank) {
-            Some(r as usize * 8 + f)
+            Some((7 - r as usize) * 8 + f)
         } else {
             None
         };
 
+        // Three-check FENs may carry the checks each side has left, as in `2+3`
+        let mut checks = [0; 2];
+        if let Some(remaining) = fields.clone().next().filter(|f| f.contains('+')) {
+            fields.next();
+            let (white, black) = remaining.split_once('+').unwrap();
+            checks = [
+                3 - black.parse::<u8>().unwrap().min(3),
+                3 - white.parse::<u8>().unwrap().min(3),
+            ];
+        }
+
         let halfmove = fields.next().unwrap().parse().unwrap();
         let fullmove = fields.next().unwrap().parse().unwrap();
 
@@ -83,172 +571,1147 @@ impl Game {
             bitboards,
             turn,
             castling,
+            chess960: !is_standard_castling(castling, &bitboards),
             enpassant,
             halfmove,
             fullmove,
             legal_moves: [0; 64],
-            winner: None,
-            stalemate: false,
+            variant,
+            checks,
+            pockets,
+            promoted,
+            drops: 0,
+            outcome: None,
+            clock: None,
         };
 
-        game.legal_moves = gen_all_moves(game.turn, &game.bitboards, enpassant, castling);
+        game.calc_legal_moves();
         game
     }
 
-    pub fn turn(&mut self) {
-        if !self.turn {
-            self.fullmove += 1;
+    /// `with_variant`, nothing if `fen` is malformed.
+    pub fn try_with_variant(fen: &str, variant: &'static dyn Variant) -> Option<Self> {
+        is_valid_fen(fen).then(|| Game::with_variant(fen, variant))
+    }
+
+    /// `from_fen`, nothing if `fen` is malformed.
+    pub fn try_from_fen(fen: &str) -> Option<Self> {
+        Game::try_with_variant(fen, &Standard)
+    }
+
+    /// A copy of the board, side to move, castling rights, en passant square and counters.
+    pub fn position(&self) -> Position {
+        Position {
+            board: self.bitboards,
+            turn: self.turn,
+            castling: self.castling,
+            enpassant: self.enpassant,
+            halfmove: self.halfmove,
+            fullmove: self.fullmove,
         }
-        self.turn = !self.turn
     }
 
-    pub fn calc_legal_moves(&mut self) {
-        self.legal_moves = gen_all_moves(self.turn, &self.bitboards, self.enpassant, self.castling);
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
     }
 
-    #[wasm_bindgen]
-    pub fn get_legal_moves(&mut self) -> Vec<Int32Array> {
-        self.legal_moves
-            .into_iter()
-            .map(|moves| {
-                let mut v = vec![];
-                apply!(moves, i -> v.push(i as i32));
-                Int32Array::from(&v[..])
-            })
-            .collect()
+    /// Attaches `clock` and starts it for the side to move, unless the game is over.
+    pub fn set_clock(&mut self, mut clock: Clock) {
+        if self.outcome.is_none() {
+            clock.start(self.turn);
+        }
+        self.clock = Some(clock);
     }
 
-    #[wasm_bindgen]
-    pub fn send_board(&self) -> String {
-        serde_json::to_string(&self.bitboards).expect("oh shit")
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    /// Hands the move over on the clock after a move from the UI, or stops it at the end of
+    /// the game. Searches play moves without it.
+    fn press_clock(&mut self) {
+        if let Some(clock) = &mut self.clock {
+            match self.outcome {
+                Some(_) => clock.stop(),
+                None => clock.press(),
+            }
+        }
+    }
+
+    /// Whether `color` could mate by some series of legal moves: anything but a bare king,
+    /// a king and a single minor piece against a bare king, or bishops that all stand on
+    /// squares of one colour, whichever side they belong to.
+    pub fn has_mating_material(&self, color: Color) -> bool {
+        let bitboards = &self.bitboards;
+        let pieces = bitboards.color(color) & !bitboards.kings;
+        let minors = pieces & (bitboards.knights | bitboards.bishops);
+        let foes = bitboards.color(!color) & !bitboards.kings;
+        let all = pieces | foes;
+        if all == bitboards.bishops & all && (all & LIGHT_SQUARES == 0 || all & !LIGHT_SQUARES == 0)
+        {
+            return false;
+        }
+        pieces != minors || minors.count_ones() > 1 || (minors != 0 && foes != 0)
     }
 
-    fn evaluate_move(&self, r#move: &Move) {
-        let mut response = MoveResponse {
-            updates: vec![(r#move.from_sq, r#move.to_sq as i32)],
+    /// UCI long algebraic notation of a legal move.
+    pub fn uci_move(&self, from: usize, to: usize, promotion: Option<Piece>) -> String {
+        let to = match self.castling_path(from, to) {
+            Some(path) if self.chess960 => path.rook,
+            Some(path) => path.king_to,
+            None => to,
         };
+        let promotion = promotion.map_or(String::new(), |piece| piece.letter().to_string());
+        format!("{}{}{promotion}", square_name(from), square_name(to))
+    }
 
-        let (consts, foes) = if self.turn {
-            (Consts::WHITE, self.bitboards.blacks)
-        } else {
-            (Consts::BLACK, self.bitboards.whites)
+    /// Parses a UCI move. The move is not checked for legality.
+    pub fn parse_uci_move(&self, uci: &str) -> Option<Move> {
+        let from = parse_square(uci.get(0..2)?)?;
+        let mut to = parse_square(uci.get(2..4)?)?;
+        let promotion = match uci.get(4..)? {
+            "" => None,
+            letter => Some(Piece::from_letter(letter.parse().ok()?)?),
         };
+        // king-takes-rook is always understood, whatever `UCI_Chess960` says
+        let friends = self.bitboards.color(self.turn);
+        if self.bitboards.kings & friends & (1 << from) != 0
+            && self.castling & friends & (1 << to) != 0
+        {
+            to = CastlingPath::new(from, to).target();
+        }
+        Some((from, to, promotion))
+    }
+
+    /// UCI notation of a drop, as in `N@f3`.
+    pub fn uci_drop(&self, piece: Piece, to: usize) -> String {
+        format!(
+            "{}@{}",
+            piece.letter().to_ascii_uppercase(),
+            square_name(to)
+        )
+    }
 
-        if self.bitboards.pawns & r#move.from_bb != 0 {
-            if let Some(enpassant) = self.enpassant {
-                if r#move.to_sq == enpassant {
-                    let captured_pawn = enpassant + (8 * consts.direction as usize);
-                    let captured_pawn_bb = 1 << captured_pawn;
-                    response.updates.push((captured_pawn, -1));
+    /// Parses a UCI drop into the piece and the square it is dropped on.
+    pub fn parse_uci_drop(&self, uci: &str) -> Option<(Piece, usize)> {
+        let (piece, to) = uci.split_once('@')?;
+        let mut letters = piece.chars();
+        let piece = letters.next().and_then(Piece::from_letter)?;
+        if letters.next().is_some() || piece == Piece::King {
+            return None;
+        }
+        Some((piece, parse_square(to)?))
+    }
+
+    /// Parses a legal move in standard algebraic notation (`Nbd7`, `exd6`, `e8=Q+`, `O-O`)
+    /// into one of its legal moves.
+    pub fn parse_san(&self, san: &str) -> Option<Move> {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        let friends = self.bitboards.color(self.turn);
+
+        let queenside = match san {
+            "O-O" | "0-0" => Some(false),
+            "O-O-O" | "0-0-0" => Some(true),
+            _ => None,
+        };
+        if let Some(queenside) = queenside {
+            let king = self.bitboards.kings & friends;
+            if king == 0 {
+                return None;
+            }
+            let king = bsf(king) as usize;
+            let mut castling = None;
+            apply!(self.castling & friends & self.bitboards.rooks, r -> {
+                let to = CastlingPath::new(king, r).target();
+                if (r < king) == queenside && self.legal_moves[king] & (1 << to) != 0 {
+                    castling = Some((king, to, None));
                 }
-            } else if (r#move.to_sq as i32 - r#move.from_sq as i32).abs() == 16 {
-                self.enpassant = Some((r#move.to_sq as i32 + 8 * consts.direction as i32) as usize);
-            } else if r#move.to_bb & consts.eighth_rank != 0 {
-                {}
-            }
-        } else if self.bitboards.kings & r#move.from_bb != 0 {
-            self.castling &= !(consts.ks_castle | consts.qs_castle);
-            match r#move.to_sq as i32 - r#move.from_sq as i32 {
-                2 => response
-                    .updates
-                    .push((r#move.to_sq + 1, r#move.to_sq as i32 - 1)),
-                -2 => response
-                    .updates
-                    .push((r#move.to_sq - 2, r#move.to_sq as i32 + 1)),
-                _ => {}
-            }
-        } else if consts.ks_rook & from_bb != 0 {
-            self.castling &= !consts.ks_castle;
-        } else if consts.qs_rook & from_bb != 0 {
-            self.castling &= !consts.qs_castle;
-        }
-    }
-
-    #[wasm_bindgen]
-    pub fn input_move(&mut self, from: usize, to: usize, promotion: usize) -> String {
-        let from_bb: u64 = 1 << from;
-        let to_bb: u64 = 1 << to;
-        let from_to_bb = from_bb ^ to_bb;
-        if self.legal_moves[from] & to_bb == 0 {
-            let response = (false, String::from("Invalid move"));
-            return serde_json::to_string(&response).unwrap();
+            });
+            return castling;
+        }
+
+        let mut chars: Vec<char> = san.chars().filter(|c| !matches!(c, 'x' | '-')).collect();
+        let mut promotion = None;
+        let last = *chars.last()?;
+        if chars.len() > 2 && last.is_ascii_uppercase() {
+            if let Some(piece) = Piece::from_letter(last) {
+                promotion = Some(piece);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+        let piece = match chars.first()? {
+            c if c.is_ascii_uppercase() => {
+                let piece = Piece::from_letter(*c)?;
+                chars.remove(0);
+                piece
+            }
+            _ => Piece::Pawn,
         };
+        let split = chars.len().checked_sub(2)?;
+        let to = parse_square(&chars[split..].iter().collect::<String>())?;
+
+        let mut candidates = self.bitboards.pieces(piece) & friends;
+        for c in &chars[..split] {
+            candidates &= match c {
+                'a'..='h' => A_FILE << (*c as u8 - b'a'),
+                '1'..='8' => 0xFF << (8 * (b'8' - *c as u8)),
+                _ => return None,
+            };
+        }
+        let mut found = None;
+        apply!(candidates, from -> {
+            if self.legal_moves[from] & (1 << to) != 0 {
+                if found.is_some() {
+                    return None;
+                }
+                found = Some((from, to, promotion));
+            }
+        });
+        found
+    }
+
+    /// The castling move `from -> to` in the `legal_moves` encoding, if it is one.
+    /// A king never reaches a castling target by a plain move: it is either two squares away
+    /// or the castling rook's own square.
+    pub(crate) fn castling_path(&self, from: usize, to: usize) -> Option<CastlingPath> {
+        if self.bitboards.kings & (1 << from) == 0 {
+            return None;
+        }
+        let friends = self.bitboards.color(self.turn);
+        let mut castling = None;
+        apply!(self.castling & friends & self.bitboards.rooks, r -> {
+            let path = CastlingPath::new(from, r);
+            if path.target() == to && from & !7 == r & !7 {
+                castling = Some(path);
+            }
+        });
+        castling
+    }
 
-        let (consts, foes) = if self.turn {
-            (Consts::WHITE, self.bitboards.blacks)
+    /// Whether `from -> to` takes a pawn of the side to move to its last rank.
+    pub(crate) fn is_promotion(&self, from: usize, to: usize) -> bool {
+        let (eighth_rank, friends) = if self.turn == Color::White {
+            (Consts::WHITE.eighth_rank, self.bitboards.whites)
         } else {
-            (Consts::BLACK, self.bitboards.whites)
+            (Consts::BLACK.eighth_rank, self.bitboards.blacks)
         };
+        self.bitboards.pawns & friends & (1 << from) != 0 && eighth_rank & (1 << to) != 0
+    }
+
+    /// Pieces a pawn may promote to, kings included where the variant allows them.
+    pub fn promotion_pieces(&self) -> &'static [Piece] {
+        const PIECES: [Piece; 5] = [
+            Piece::Queen,
+            Piece::Rook,
+            Piece::Bishop,
+            Piece::Knight,
+            Piece::King,
+        ];
+        if self.variant.king_promotion() {
+            &PIECES
+        } else {
+            &PIECES[..4]
+        }
+    }
+
+    /// Legal moves of the side to move, promotions once per promotion piece. Drops are left out.
+    pub fn moves(&self) -> MoveList {
+        let mut moves = vec![];
+        for (from, &targets) in self.legal_moves.iter().enumerate() {
+            apply!(targets, to -> {
+                if self.is_promotion(from, to) {
+                    moves.extend(self.promotion_pieces().iter().map(|&piece| (from, to, Some(piece))));
+                } else {
+                    moves.push((from, to, None));
+                }
+            });
+        }
+        moves
+    }
 
-        let mut response = MoveResponse {
-            updates: vec![(from, to as i32)],
+    /// Counts the leaf nodes of the legal move tree `depth` plies deep.
+    /// Legal moves, then legal drops of the pieces in hand.
+    pub fn steps(&self) -> Vec<Step> {
+        let mut steps: Vec<Step> = self.moves().into_iter().map(Step::Move).collect();
+        for piece in Piece::POCKET {
+            apply!(self.drop_squares(piece), to -> steps.push(Step::Drop(piece, to)));
+        }
+        steps
+    }
+
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut nodes = 0;
+        for (from, to, promotion) in self.moves() {
+            let mut game = self.clone();
+            game.make_move(from, to, promotion);
+            nodes += game.perft(depth - 1);
+        }
+        for piece in Piece::POCKET {
+            apply!(self.drop_squares(piece), to -> {
+                let mut game = self.clone();
+                game.drop_piece(piece, to);
+                nodes += game.perft(depth - 1);
+            });
+        }
+        nodes
+    }
+
+    /// Plays a legal move. Promotions must name one of `promotion_pieces`, other moves none.
+    pub(crate) fn make_move(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<Piece>,
+    ) -> Option<MoveResult> {
+        if self.legal_moves[from] & (1 << to) == 0 {
+            return None;
+        };
+        let valid = match promotion {
+            Some(piece) => self.promotion_pieces().contains(&piece),
+            None => true,
         };
+        if !valid || self.is_promotion(from, to) != promotion.is_some() {
+            return None;
+        }
+        let san = self.san_body(from, to, promotion);
+        let (played, removed) = self.play(from, to, promotion);
+        self.calc_legal_moves();
+        Some(self.move_result(&played, removed, san))
+    }
 
-        *self.bitboards.get_color_bb_mut(self.turn) ^= from_to_bb;
-        if foes & to_bb != 0 {
-            *self.bitboards.get_color_bb_mut(!self.turn) ^= to_bb;
-            *self.bitboards.get_piece_bb_mut(to_bb) ^= to_bb;
+    /// Describes the move just played, adding check and mate to its SAN.
+    fn move_result(&self, played: &PlayedMove, removed: Vec<usize>, mut san: String) -> MoveResult {
+        let check = self.is_check();
+        let mate = check && self.outcome == Some(Outcome::Winner(!self.turn));
+        if mate {
+            san.push('#');
+        } else if check {
+            san.push('+');
         }
+        let (from, to, rook) = match played.castling {
+            Some(path) => (path.king, path.king_to, Some((path.rook, path.rook_to))),
+            None => (played.from, played.to, None),
+        };
+        MoveResult {
+            from: Some(from),
+            to,
+            piece: played.piece,
+            captured: played.captured,
+            rook,
+            promotion: played.promotion,
+            removed,
+            check,
+            mate,
+            san,
+        }
+    }
 
-        let enpassant = self.enpassant;
-        self.enpassant = None;
+    /// SAN of a legal move without its check or mate suffix.
+    fn san_body(&self, from: usize, to: usize, promotion: Option<Piece>) -> String {
+        if let Some(path) = self.castling_path(from, to) {
+            return if path.is_kingside() { "O-O" } else { "O-O-O" }.to_string();
+        }
+        let (piece, _) = self.bitboards.piece_at(from).unwrap();
+        let foes = self.bitboards.color(!self.turn);
+        let mut san = String::new();
+        if piece == Piece::Pawn {
+            let capture = foes & (1 << to) != 0 || self.enpassant == Some(to);
+            if capture {
+                san.push(square_name(from).as_bytes()[0] as char);
+                san.push('x');
+            }
+            san.push_str(&square_name(to));
+            if let Some(promotion) = promotion {
+                san.push('=');
+                san.push(promotion.letter().to_ascii_uppercase());
+            }
+            return san;
+        }
 
-        if self.bitboards.pawns & from_bb != 0 {
-            if let Some(enpassant) = enpassant {
-                if to == enpassant {
-                    let captured_pawn = enpassant + (8 * consts.direction as usize);
-                    let captured_pawn_bb = 1 << captured_pawn;
-                    self.bitboards.pawns ^= captured_pawn_bb;
-                    *self.bitboards.get_color_bb_mut(!self.turn) ^= captured_pawn_bb;
-                    response.updates.push((captured_pawn, -1));
+        san.push(piece.letter().to_ascii_uppercase());
+        let mut rivals = 0;
+        let friends = self.bitboards.pieces(piece) & !foes & !(1 << from);
+        apply!(friends, other -> {
+            if self.legal_moves[other] & (1 << to) != 0 && self.castling_path(other, to).is_none() {
+                rivals |= 1 << other;
+            }
+        });
+        if rivals != 0 {
+            let name = square_name(from);
+            if rivals & A_FILE << (from % 8) == 0 {
+                san.push_str(&name[..1]);
+            } else if rivals & 0xFF << (from & !7) == 0 {
+                san.push_str(&name[1..]);
+            } else {
+                san.push_str(&name);
+            }
+        }
+        if foes & (1 << to) != 0 {
+            san.push('x');
+        }
+        san.push_str(&square_name(to));
+        san
+    }
+
+    /// SAN of a legal move, `None` if it is not one.
+    pub fn san(&self, from: usize, to: usize, promotion: Option<Piece>) -> Option<String> {
+        let mut game = self.clone();
+        game.make_move(from, to, promotion).map(|result| result.san)
+    }
+
+    /// Plays `from -> to` without checking it and without updating `legal_moves`, so variants
+    /// can try out pseudo-legal moves on a copy of the game.
+    pub(crate) fn play(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<Piece>,
+    ) -> (PlayedMove, Vec<usize>) {
+        let enpassant = self.enpassant.take();
+        let played = match self.castling_path(from, to) {
+            Some(path) => {
+                self.castle(path);
+                PlayedMove {
+                    from,
+                    to,
+                    piece: Piece::King,
+                    captured: None,
+                    captured_promoted: false,
+                    castling: Some(path),
+                    promotion: None,
                 }
-            } else if (to as i32 - from as i32).abs() == 16 {
+            }
+            None => self.move_piece(from, to, promotion, enpassant),
+        };
+        self.turn();
+
+        let mut removed = vec![];
+        let variant = self.variant;
+        variant.after_move(self, &played, &mut removed);
+        (played, removed)
+    }
+
+    /// Drops `piece` from the pocket of the side to move onto `to` (Crazyhouse).
+    /// Plays a move or a drop, like `make_move` and `drop_piece`.
+    pub(crate) fn make_step(&mut self, step: Step) -> Option<MoveResult> {
+        match step {
+            Step::Move((from, to, promotion)) => self.make_move(from, to, promotion),
+            Step::Drop(piece, to) => self.drop_piece(piece, to),
+        }
+    }
+
+    pub(crate) fn drop_piece(&mut self, piece: Piece, to: usize) -> Option<MoveResult> {
+        if self.drop_squares(piece) & (1 << to) == 0 {
+            return None;
+        }
+        let san = match piece {
+            Piece::Pawn => format!("@{}", square_name(to)),
+            _ => self.uci_drop(piece, to),
+        };
+        self.enpassant = None;
+        self.pockets[self.turn.index()][piece as usize - 1] -= 1;
+        self.bitboards.put(to, piece, self.turn);
+        if piece == Piece::Pawn {
+            self.halfmove = 0;
+        } else {
+            self.halfmove += 1;
+        }
+        self.turn();
+        self.calc_legal_moves();
+        let played = PlayedMove {
+            from: to,
+            to,
+            piece,
+            captured: None,
+            captured_promoted: false,
+            castling: None,
+            promotion: None,
+        };
+        let mut result = self.move_result(&played, vec![], san);
+        result.from = None;
+        Some(result)
+    }
+
+    /// Squares the side to move may drop `piece` on: none if it has no such piece in hand.
+    pub fn drop_squares(&self, piece: Piece) -> u64 {
+        let back_ranks = Consts::WHITE.first_rank | Consts::WHITE.eighth_rank;
+        match self.pockets[self.turn.index()].get(piece as usize - 1) {
+            Some(0) | None => 0,
+            Some(_) if piece == Piece::Pawn => self.drops & !back_ranks,
+            Some(_) => self.drops,
+        }
+    }
+
+    pub(crate) fn has_drops(&self) -> bool {
+        Piece::POCKET
+            .into_iter()
+            .any(|piece| self.drop_squares(piece) != 0)
+    }
+
+    fn castle(&mut self, path: CastlingPath) {
+        // both pieces leave before either lands: in Chess960 they may swap squares
+        self.bitboards.remove(path.king);
+        self.bitboards.remove(path.rook);
+        self.bitboards.put(path.king_to, Piece::King, self.turn);
+        self.bitboards.put(path.rook_to, Piece::Rook, self.turn);
+        let consts = if self.turn == Color::White {
+            Consts::WHITE
+        } else {
+            Consts::BLACK
+        };
+        self.castling &= !consts.first_rank;
+        self.halfmove += 1;
+    }
+
+    /// Moves a piece, handling captures, en passant and promotion.
+    fn move_piece(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<Piece>,
+        enpassant: Option<usize>,
+    ) -> PlayedMove {
+        let (piece, color) = self.bitboards.remove(from).unwrap();
+        let from_bb: u64 = 1 << from;
+        let to_bb: u64 = 1 << to;
+        let from_to_bb = from_bb ^ to_bb;
+
+        let consts = if color == Color::White {
+            Consts::WHITE
+        } else {
+            Consts::BLACK
+        };
+
+        let mut captured = None;
+        let mut promoted_to = None;
+        let captured_promoted = self.promoted & to_bb != 0;
+        self.promoted &= !to_bb;
+        if self.promoted & from_bb != 0 {
+            self.promoted ^= from_to_bb;
+        }
+
+        if let Some((victim, _)) = self.bitboards.remove(to) {
+            // a captured rook takes its castling right along
+            self.castling &= !to_bb;
+            captured = Some((to, victim));
+        }
+        if piece == Piece::Pawn || captured.is_some() {
+            self.halfmove = 0;
+        } else {
+            self.halfmove += 1;
+        }
+
+        let mut landing = piece;
+        if piece == Piece::Pawn {
+            if enpassant == Some(to) {
+                let captured_pawn = (to as i32 + 8 * consts.direction as i32) as usize;
+                self.bitboards.remove(captured_pawn);
+                captured = Some((captured_pawn, Piece::Pawn));
+            } else if from_bb & consts.second_rank != 0 && (to as i32 - from as i32).abs() == 16 {
                 self.enpassant = Some((to as i32 + 8 * consts.direction as i32) as usize);
             } else if to_bb & consts.eighth_rank != 0 {
-                self.bitboards.pawns ^= to_bb;
-                match promotion {
-                    1 => {
-                        self.bitboards.queens |= to_bb;
-                    }
-                    2 => {
-                        self.bitboards.rooks |= to_bb;
-                    }
-                    3 => {
-                        self.bitboards.bishops |= to_bb;
-                    }
-                    4 => {
-                        self.bitboards.knights |= to_bb;
-                    }
-                    _ => {}
+                self.promoted |= to_bb;
+                if let Some(piece) = promotion {
+                    landing = piece;
+                    promoted_to = Some(piece);
                 }
             }
-        } else if self.bitboards.kings & from_bb != 0 {
-            self.castling &= !(consts.ks_castle | consts.qs_castle);
-            match to as i32 - from as i32 {
-                2 => {
-                    self.bitboards.rooks ^= consts.ks_rook | (consts.ks_rook >> 2);
-                    *self.bitboards.get_color_bb_mut(self.turn) ^=
-                        consts.ks_rook | (consts.ks_rook >> 2);
-                    response.updates.push((to + 1, to as i32 - 1))
-                }
-                -2 => {
-                    self.bitboards.rooks ^= consts.ks_rook | (consts.ks_rook >> 2);
-                    *self.bitboards.get_color_bb_mut(self.turn) ^=
-                        consts.ks_rook | (consts.ks_rook << 3);
-                    response.updates.push((to - 2, to as i32 + 1))
-                }
-                _ => {}
+        } else if piece == Piece::King {
+            self.castling &= !consts.first_rank;
+        } else {
+            self.castling &= !from_bb;
+        }
+
+        self.bitboards.put(to, landing, color);
+        PlayedMove {
+            from,
+            to,
+            piece,
+            captured,
+            captured_promoted,
+            castling: None,
+            promotion: promoted_to,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perft_startpos() {
+        let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(game.perft(1), 20);
+        assert_eq!(game.perft(2), 400);
+        assert_eq!(game.perft(3), 8902);
+    }
+
+    #[test]
+    fn perft_enpassant_pins() {
+        // chessprogramming.org "Position 3": horizontal en passant pins along the 5th rank.
+        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        assert_eq!(game.perft(1), 14);
+        assert_eq!(game.perft(2), 191);
+        assert_eq!(game.perft(3), 2812);
+        assert_eq!(game.perft(4), 43238);
+    }
+
+    #[test]
+    fn perft_enpassant_evasions() {
+        // the double-pushed pawn gives check and may be taken en passant
+        for (fen, counts) in [
+            ("8/8/8/5k2/3p4/8/4P3/4K3 w - - 0 1", [6, 54, 343, 2810]),
+            ("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1", [9, 50, 379, 2369]),
+            ("8/8/8/2k5/3Pp3/8/6B1/4K3 b - d3 0 1", [9, 99, 701, 8066]),
+            ("4k3/8/8/2KpP3/8/8/8/8 w - d6 0 1", [9, 45, 336, 1976]),
+        ] {
+            let game = Game::from_fen(fen);
+            for (depth, nodes) in (1..).zip(counts) {
+                assert_eq!(game.perft(depth), nodes, "{fen} depth {depth}");
             }
-        } else if consts.ks_rook & from_bb != 0 {
-            self.castling &= !consts.ks_castle;
-        } else if consts.qs_rook & from_bb != 0 {
-            self.castling &= !consts.qs_castle;
         }
+        let mut game = Game::from_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+        assert!(game.make_move(36, 43, None).is_some());
+        assert_eq!(game.to_fen(), "8/8/8/2k5/8/3p4/8/4K3 w - - 0 2");
+    }
 
-        *self.bitboards.get_piece_bb_mut(from_bb) ^= from_to_bb;
+    #[test]
+    fn enpassant_square_from_fen() {
+        let game = Game::from_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3");
+        assert_eq!(game.enpassant, Some(19));
+        let game = Game::from_fen("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 3");
+        assert_eq!(game.enpassant, Some(43));
+    }
 
-        self.turn();
-        self.calc_legal_moves();
-        serde_json::to_string(&(true, response)).unwrap()
+    #[test]
+    fn enpassant_capture_removes_pawn() {
+        let mut game =
+            Game::from_fen("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 3");
+        assert!(game.make_move(36, 43, None).is_some());
+        assert_eq!(game.bitboards.pawns & (1 << 35), 0);
+        assert_eq!(game.bitboards.whites & (1 << 35), 0);
+        assert_ne!(game.bitboards.blacks & (1 << 43), 0);
+        assert_eq!(game.enpassant, None);
+
+        let mut game =
+            Game::from_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3");
+        assert!(game.make_move(28, 19, None).is_some());
+        assert_eq!(game.bitboards.pawns & (1 << 27), 0);
+        assert_eq!(game.bitboards.blacks & (1 << 27), 0);
+    }
+
+    #[test]
+    fn double_push_sets_enpassant_after_enpassant() {
+        // A pending en passant square must not swallow the next double push.
+        let mut game = Game::from_fen("rnbqkbnr/pppp1ppp/8/8/4p3/8/PPPPPPPP/RNBQKBNR w KQkq - 0 3");
+        assert!(game.make_move(51, 35, None).is_some());
+        assert_eq!(game.enpassant, Some(43));
+        assert!(game.make_move(9, 25, None).is_some());
+        assert_eq!(game.enpassant, Some(17));
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        let game =
+            Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(game.perft(1), 48);
+        assert_eq!(game.perft(2), 2039);
+        assert_eq!(game.perft(3), 97862);
+    }
+
+    #[test]
+    fn perft_chess960() {
+        let positions = [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18002],
+            ),
+            (
+                "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+                [29, 502, 14569],
+            ),
+            // king and rook swap squares
+            ("k7/8/8/8/8/8/8/5KR1 w G - 0 1", [13, 36, 639]),
+            // the castling rook shields the king's destination
+            ("4k3/8/8/8/8/8/8/rR2K3 w B - 0 1", [8, 97, 1400]),
+            ("k7/8/8/8/8/8/8/2RK3q w C - 0 1", [3, 63, 751]),
+        ];
+        for (fen, nodes) in positions {
+            let game = Game::from_fen(fen);
+            for (depth, nodes) in nodes.into_iter().enumerate() {
+                assert_eq!(
+                    game.perft(depth as u32 + 1),
+                    nodes,
+                    "{fen} depth {}",
+                    depth + 1
+                );
+            }
+        }
+    }
+
+    /// Castling targets of the white king on e1.
+    fn castling_targets(fen: &str) -> u64 {
+        Game::from_fen(fen).legal_moves[60] & (1 << 58 | 1 << 62)
+    }
+
+    #[test]
+    fn castling_conditions() {
+        const KINGSIDE: u64 = 1 << 62;
+        const QUEENSIDE: u64 = 1 << 58;
+        for (fen, targets) in [
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", KINGSIDE | QUEENSIDE),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1", KINGSIDE),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w Qk - 0 1", QUEENSIDE),
+            // in check
+            ("4k3/8/8/8/4r3/8/8/R3K2R w KQ - 0 1", 0),
+            // f1 and d1 attacked: the king would pass through check
+            ("4k3/8/8/8/5r2/8/8/R3K2R w KQ - 0 1", QUEENSIDE),
+            ("4k3/8/8/8/3r4/8/8/R3K2R w KQ - 0 1", KINGSIDE),
+            // g1 and c1 attacked: the king would land in check
+            ("4k3/8/8/8/6r1/8/8/R3K2R w KQ - 0 1", QUEENSIDE),
+            ("4k3/8/8/8/2r5/8/8/R3K2R w KQ - 0 1", KINGSIDE),
+            // b1 attacked: only the rook crosses it
+            ("4k3/8/8/8/1r6/8/8/R3K2R w KQ - 0 1", KINGSIDE | QUEENSIDE),
+            // a1 and h1 attacked: the rook may castle out of attack
+            ("4k3/7r/8/8/8/8/8/R3K2R w K - 0 1", KINGSIDE),
+            ("4k3/r7/8/8/8/8/8/R3K2R w Q - 0 1", QUEENSIDE),
+            // bishops hitting the rook and a square the king crosses or lands on
+            ("4k3/8/8/8/8/8/6b1/R3K2R w KQ - 0 1", QUEENSIDE),
+            ("4k3/8/8/8/8/8/1b6/R3K2R w KQ - 0 1", KINGSIDE),
+            // occupied squares, by friends or foes
+            ("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1", KINGSIDE),
+            ("4k3/8/8/8/8/8/8/Rn2K2R w KQ - 0 1", KINGSIDE),
+            ("4k3/8/8/8/8/8/8/R3Kn1R w KQ - 0 1", QUEENSIDE),
+            ("4k3/8/8/8/8/8/8/R3K1NR w KQ - 0 1", QUEENSIDE),
+            ("4k3/8/8/8/8/8/8/R2qK2R w KQ - 0 1", 0),
+            // a right without its rook
+            ("4k3/8/8/8/8/8/8/R3K2N w KQ - 0 1", QUEENSIDE),
+        ] {
+            assert_eq!(castling_targets(fen), targets, "{fen}");
+        }
+    }
+
+    #[test]
+    fn castling_moves_the_right_rook() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        game.make_move(60, 58, None).unwrap();
+        assert_eq!(
+            game.bitboards.rooks & game.bitboards.whites,
+            1 << 59 | 1 << 63
+        );
+        assert_eq!(game.bitboards.kings & game.bitboards.whites, 1 << 58);
+        game.make_move(4, 6, None).unwrap();
+        assert_eq!(game.bitboards.rooks & game.bitboards.blacks, 1 | 1 << 5);
+        assert_eq!(game.castling, 0);
+        assert_eq!(game.to_fen(), "r4rk1/8/8/8/8/8/8/2KR3R w - - 2 2");
+    }
+
+    #[test]
+    fn castling_rights_revoked() {
+        let moves = |fen: &str, moves: &[(usize, usize)]| {
+            let mut game = Game::from_fen(fen);
+            for &(from, to) in moves {
+                game.make_move(from, to, None).unwrap();
+            }
+            game.to_fen()
+        };
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        // king and rook moves
+        assert!(moves(fen, &[(60, 61)]).contains(" b kq "));
+        assert!(moves(fen, &[(63, 62)]).contains(" b Qkq "));
+        assert!(moves(fen, &[(56, 57)]).contains(" b Kkq "));
+        // the rooks take each other: both lose the right
+        assert!(moves(fen, &[(63, 7)]).contains(" b Qq "));
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert!(moves(fen, &[(7, 63)]).contains(" w Qq "));
+        // a rook captured by another piece, and the rook coming back
+        let fen = "r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1";
+        assert!(moves(fen, &[(54, 63)]).contains(" w Qkq "));
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1");
+        game.make_move(54, 63, None).unwrap();
+        game.make_move(56, 57, None).unwrap();
+        game.make_move(0, 56, None).unwrap();
+        assert_eq!(game.castling, 1 << 7);
+    }
+
+    #[test]
+    fn castling_rights_from_fen() {
+        let standard = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let game = Game::from_fen(standard);
+        assert_eq!(game.castling, 1 << 0 | 1 << 7 | 1 << 56 | 1 << 63);
+        assert!(!game.chess960);
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1");
+        assert_eq!(game.castling, 1 << 0 | 1 << 7 | 1 << 56 | 1 << 63);
+        assert!(!game.chess960);
+
+        // X-FEN picks the outermost rook, Shredder-FEN names the file
+        let game = Game::from_fen("1r1k2rr/8/8/8/8/8/8/1R1K2RR w KQkq - 0 1");
+        assert_eq!(game.castling, 1 << 1 | 1 << 7 | 1 << 57 | 1 << 63);
+        assert!(game.chess960);
+        let game = Game::from_fen("1r1k2rr/8/8/8/8/8/8/1R1K2RR w GBg - 0 1");
+        assert_eq!(game.castling, 1 << 6 | 1 << 57 | 1 << 62);
+    }
+
+    #[test]
+    fn move_results() {
+        let mut game =
+            Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let result = game.make_move(60, 58, None).unwrap();
+        assert_eq!((result.from, result.to), (Some(60), 58));
+        assert_eq!((result.piece, result.rook), (Piece::King, Some((56, 59))));
+        assert_eq!(result.san, "O-O-O");
+        // b4xa3 en passant after a2-a4
+        let mut game =
+            Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1");
+        let result = game.make_move(33, 40, None).unwrap();
+        assert_eq!(result.captured, Some((32, Piece::Pawn)));
+        assert_eq!(result.san, "bxa3");
+        assert!(result.removed.is_empty() && !result.check);
+
+        for (fen, (from, to, promotion), san) in [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                (28, 13, None),
+                "Nxf7",
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                (42, 25, None),
+                "Nb5",
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                (27, 20, None),
+                "dxe6",
+            ),
+            ("4k3/8/8/8/8/8/8/R4R1K w - - 0 1", (56, 58, None), "Rac1"),
+            ("4k3/8/8/8/R7/8/8/R6K w - - 0 1", (56, 48, None), "R1a2"),
+            ("8/8/8/7k/8/Q7/8/Q1Q4K w - - 0 1", (56, 42, None), "Qa1c3"),
+            (
+                "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+                (56, 0, None),
+                "Ra8#",
+            ),
+            (
+                "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1",
+                (9, 1, Some(Piece::Queen)),
+                "b8=Q+",
+            ),
+            (
+                "2n1k3/1P6/8/8/8/8/8/4K3 w - - 0 1",
+                (9, 2, Some(Piece::Knight)),
+                "bxc8=N",
+            ),
+        ] {
+            let game = Game::from_fen(fen);
+            assert_eq!(game.san(from, to, promotion).as_deref(), Some(san), "{fen}");
+            assert_eq!(game.parse_san(san), Some((from, to, promotion)), "{fen}");
+        }
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        assert_eq!(game.san(56, 1, None), None);
+        let result = game.clone().make_move(56, 0, None).unwrap();
+        assert!(result.check && result.mate);
+
+        let mut game = Game::new(
+            "4k3/8/8/8/8/8/8/4K3[Np] w - - 0 1",
+            Some("crazyhouse".into()),
+        )
+        .unwrap();
+        let result = game.input_drop(Piece::Knight, 19).unwrap();
+        assert_eq!(
+            (result.from, result.to, result.piece),
+            (None, 19, Piece::Knight)
+        );
+        assert_eq!(result.san, "N@d6+");
+        game.make_move(4, 5, None).unwrap();
+        game.make_move(60, 59, None).unwrap();
+        let result = game.input_drop(Piece::Pawn, 36).unwrap();
+        assert_eq!(result.san, "@e4");
+        assert!(game.input_drop(Piece::Rook, 40).is_none());
+    }
+
+    #[test]
+    fn pieces_and_colors() {
+        let mut game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        assert_eq!(game.side_to_move(), Color::White);
+        assert_eq!(
+            game.bitboards.piece_at(56),
+            Some((Piece::Rook, Color::White))
+        );
+        assert_eq!(
+            game.bitboards.piece_at(6),
+            Some((Piece::King, Color::Black))
+        );
+        assert_eq!(game.bitboards.piece_at(0), None);
+        assert!(game
+            .send_board()
+            .contains(r#"[56,{"kind":"rook","color":"white"}]"#));
+
+        game.make_move(56, 0, None).unwrap();
+        assert_eq!(game.side_to_move(), Color::Black);
+        assert_eq!(game.winner(), Some(Color::White));
+
+        for (piece, color) in [(Piece::Knight, Color::White), (Piece::King, Color::Black)] {
+            assert_eq!(
+                crate::letterbox::unpack(piece | color),
+                Some((piece, color))
+            );
+        }
+        assert_eq!(crate::letterbox::unpack(crate::letterbox::EMPTY), None);
+    }
+
+    #[test]
+    fn native_api() {
+        let mut game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        let position = game.position();
+        assert_eq!(position.turn, Color::White);
+        assert_eq!(
+            position.board.piece_at(56),
+            Some((Piece::Rook, Color::White))
+        );
+        assert_eq!((position.castling, position.enpassant), (0, None));
+        let moves: MoveList = game.moves();
+        assert_eq!(moves.len(), 20);
+
+        let result = game.input_move(56, 0, None).unwrap();
+        assert_eq!((result.piece, result.san.as_str()), (Piece::Rook, "Ra8#"));
+        assert_eq!(game.outcome(), Some(Outcome::Winner(Color::White)));
+        assert!(game.moves().is_empty());
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b Kq - 12 40",
+            "1r1k2rr/8/8/8/8/8/8/1R1K2RR w GQg - 0 1",
+            "8/8/8/8/8/8/8/k6K b - - 99 120",
+        ] {
+            assert_eq!(Game::from_fen(fen).to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn malformed_fens() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r2q1r1k/pp1N~2pp/8/8/8/8/PP3PPP/R2QK2R[Bnp] b KQ - 0 14",
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1/n w - - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+        ] {
+            assert!(Game::try_from_fen(fen).is_some(), "{fen}");
+        }
+        for fen in [
+            "not a fen",
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR a KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e0 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x+3 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[K] w KQkq - 0 1",
+        ] {
+            assert!(Game::try_from_fen(fen).is_none(), "{fen}");
+        }
+
+        // the UI hears what went wrong
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(Game::new(start, Some("atomic".into())).is_ok());
+        assert_eq!(
+            Game::new(start, Some("shogi".into())).err().as_deref(),
+            Some("Unknown variant: shogi")
+        );
+        assert_eq!(
+            Game::new("not a fen", None).err().as_deref(),
+            Some("Invalid FEN: not a fen")
+        );
+    }
+
+    #[test]
+    fn chess960_castling_moves() {
+        // king f1 and rook g1 trade places
+        let mut game = Game::from_fen("k7/8/8/8/8/8/8/5KR1 w G - 0 1");
+        assert_eq!(game.uci_move(61, 62, None), "f1g1");
+        assert!(game.make_move(61, 62, None).is_some());
+        assert_eq!(game.bitboards.kings & game.bitboards.whites, 1 << 62);
+        assert_eq!(game.bitboards.rooks, 1 << 61);
+        assert_eq!(game.castling, 0);
+
+        // king g1 stays put, rook h1 goes to f1
+        let mut game = Game::from_fen("k7/8/8/8/8/8/8/6KR w H - 0 1");
+        let (from, to, promotion) = game.parse_uci_move("g1h1").unwrap();
+        assert!(game.make_move(from, to, promotion).is_some());
+        assert_eq!(game.bitboards.kings & game.bitboards.whites, 1 << 62);
+        assert_eq!(game.bitboards.rooks, 1 << 61);
+    }
+
+    #[test]
+    fn uci_chess960_castling() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(game.uci_move(60, 62, None), "e1g1");
+        assert_eq!(game.uci_move(60, 58, None), "e1c1");
+        assert_eq!(game.parse_uci_move("e1g1"), Some((60, 62, None)));
+        game.set_chess960(true);
+        assert_eq!(game.uci_move(60, 62, None), "e1h1");
+        assert_eq!(game.uci_move(60, 58, None), "e1a1");
+        assert_eq!(game.parse_uci_move("e1h1"), Some((60, 62, None)));
+        // a plain king move is not castling
+        assert_eq!(game.uci_move(60, 61, None), "e1f1");
+
+        let game = Game::from_chess960(518);
+        assert!(game.chess960);
+        assert_eq!(game.perft(3), 8902);
+    }
+
+    #[test]
+    fn promotions() {
+        let game = Game::from_fen("1n5k/P7/8/8/8/8/8/K7 w - - 0 1");
+        let promotions: Vec<_> = game.moves().into_iter().filter(|m| m.0 == 8).collect();
+        assert_eq!(promotions.len(), 8);
+        assert!(promotions.contains(&(8, 1, Some(Piece::Knight))));
+        assert!(promotions.contains(&(8, 0, Some(Piece::Rook))));
+
+        let mut promoted = game.clone();
+        assert!(promoted.make_move(8, 1, None).is_none());
+        assert!(promoted.make_move(8, 1, Some(Piece::Pawn)).is_none());
+        assert!(promoted.make_move(8, 1, Some(Piece::King)).is_none());
+        assert!(promoted.make_move(56, 48, Some(Piece::Queen)).is_none());
+        assert!(promoted.make_move(8, 1, Some(Piece::Bishop)).is_some());
+        assert_eq!(promoted.to_fen(), "1B5k/8/8/8/8/8/8/K7 b - - 0 1");
+
+        let mut promoted = game.clone();
+        assert!(promoted.input_move(8, 0, None).is_none());
+        assert!(promoted.input_move(8, 0, Some(Piece::Pawn)).is_none());
+        assert!(promoted.input_move(8, 0, Some(Piece::Knight)).is_some());
+        assert_eq!(promoted.to_fen(), "Nn5k/8/8/8/8/8/8/K7 b - - 0 1");
+
+        assert_eq!(game.uci_move(8, 1, Some(Piece::Rook)), "a7b8r");
+        assert_eq!(
+            game.parse_uci_move("a7a8n"),
+            Some((8, 0, Some(Piece::Knight)))
+        );
+        assert_eq!(game.parse_uci_move("a7a8x"), None);
+    }
+
+    #[test]
+    fn san_moves() {
+        let game =
+            Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(game.parse_san("O-O"), Some((60, 62, None)));
+        assert_eq!(game.parse_san("O-O-O+"), Some((60, 58, None)));
+        assert_eq!(game.parse_san("Nxf7"), Some((28, 13, None)));
+        assert_eq!(game.parse_san("dxe6"), Some((27, 20, None)));
+        assert_eq!(game.parse_san("Qxh3"), Some((45, 47, None)));
+        assert_eq!(game.parse_san("a4"), Some((48, 32, None)));
+        assert_eq!(game.parse_san("Nb5"), Some((42, 25, None)));
+        assert_eq!(game.parse_san("Nd5"), None);
+        assert_eq!(game.parse_san("Kd1"), Some((60, 59, None)));
+
+        let game = Game::from_fen("4k3/1P6/8/8/8/8/8/R3K2R w K - 0 1");
+        assert_eq!(game.parse_san("b8=N"), Some((9, 1, Some(Piece::Knight))));
+        assert_eq!(game.parse_san("b8Q+"), Some((9, 1, Some(Piece::Queen))));
+        assert_eq!(game.parse_san("O-O-O"), None);
+        assert_eq!(game.parse_san("Rad1"), Some((56, 59, None)));
+        assert_eq!(game.parse_san("R1f1"), Some((63, 61, None)));
+        assert_eq!(game.parse_san("Rf1"), Some((63, 61, None)));
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K2R w - - 0 1");
+        assert_eq!(game.parse_san("Rd1"), Some((56, 59, None)));
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R4R1K w - - 0 1");
+        assert_eq!(game.parse_san("Rc1"), None);
+        assert_eq!(game.parse_san("Rac1"), Some((56, 58, None)));
+        assert_eq!(game.parse_san("Rfc1"), Some((61, 58, None)));
+    }
+
+    #[test]
+    fn clocks() {
+        use std::time::Duration;
+
+        let time = ManualTime::default();
+        let clock = |control| {
+            let stages = parse_time_control(control).unwrap();
+            Clock::new(stages, Mode::Fischer, Arc::new(time.clone()))
+        };
+        let mut game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(game.time_left(Color::White), None);
+        assert!(!game.check_time());
+        assert!(!game.set_time_control("5+x", "fischer"));
+        assert!(!game.set_time_control("300", "hourglass"));
+
+        game.set_clock(clock("10+1"));
+        time.advance(Duration::from_secs(3));
+        game.input_move(52, 36, None).unwrap();
+        assert_eq!(game.time_left(Color::White), Some(8000.0));
+        assert_eq!(game.clock().unwrap().running(), Some(Color::Black));
+        // searches do not touch the clock
+        game.clone().make_move(12, 28, None).unwrap();
+        time.advance(Duration::from_secs(11));
+        assert!(game.input_move(12, 28, None).is_none());
+        assert_eq!(game.outcome(), Some(Outcome::Winner(Color::White)));
+        assert!(game.moves().is_empty());
+        assert!(!game.check_time());
+
+        // a lone king cannot win on time
+        let mut game = Game::from_fen("r3k3/8/8/8/8/8/8/4K3 b - - 0 1");
+        game.set_clock(clock("1"));
+        time.advance(Duration::from_secs(1));
+        assert!(game.check_time());
+        assert_eq!(game.outcome(), Some(Outcome::Draw));
+        let mut game = Game::from_fen("r3k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        game.set_clock(clock("1"));
+        time.advance(Duration::from_secs(1));
+        assert!(game.check_time());
+        assert_eq!(game.winner(), Some(Color::Black));
+        assert!(
+            !Game::from_fen("4k3/8/8/8/8/8/8/2B1KN2 w - - 0 1").has_mating_material(Color::Black)
+        );
+        assert!(
+            Game::from_fen("4k3/8/8/8/8/8/8/2B1KN2 w - - 0 1").has_mating_material(Color::White)
+        );
+        assert!(!Game::from_fen("4k3/8/8/8/8/8/8/4KN2 w - - 0 1").has_mating_material(Color::White));
+        // bishops on one colour never mate, however many and whoever owns them
+        for fen in [
+            "4k3/8/8/8/8/8/3B4/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/B7/1B2K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K1b1 w - - 0 1",
+            "1b2kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            let game = Game::from_fen(fen);
+            assert!(!game.has_mating_material(Color::White), "{fen}");
+            assert!(!game.has_mating_material(Color::Black), "{fen}");
+        }
+        // but they do on both colours, or with a knight on the board
+        for fen in [
+            "4k3/8/8/8/8/8/8/2B2B1K w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1Kb2 w - - 0 1",
+            "4k3/8/8/8/8/8/3B4/2B1K2n w - - 0 1",
+        ] {
+            assert!(
+                Game::from_fen(fen).has_mating_material(Color::White),
+                "{fen}"
+            );
+        }
+
+        // a knight can mate a king boxed in by its own queen
+        let mut game = Game::from_fen("4k2q/8/8/8/8/8/8/4KN2 b - - 0 1");
+        assert!(game.has_mating_material(Color::White));
+        game.set_clock(clock("1"));
+        time.advance(Duration::from_secs(1));
+        assert!(game.check_time());
+        assert_eq!(game.winner(), Some(Color::White));
     }
 }
@@ -1,11 +1,26 @@
+use serde::Serialize;
 use std::ops::BitOr;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// Packed `Piece | Color` byte of an empty mailbox square.
+pub const EMPTY: u8 = 0;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Color {
     Black = 0,
     White = 8,
 }
 
+impl Color {
+    /// Index into per-color arrays: black 0, white 1.
+    pub fn index(self) -> usize {
+        (self == Color::White) as usize
+    }
+}
+
 impl BitOr for Color {
     type Output = u8;
 
@@ -24,7 +39,9 @@ impl std::ops::Not for Color {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Piece {
     Pawn   = 1,
     Knight = 2,
@@ -34,6 +51,63 @@ pub enum Piece {
     King   = 6,
 }
 
+impl Piece {
+    pub const ALL: [Piece; 6] = [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+        Piece::King,
+    ];
+
+    /// Pieces that can be held in a Crazyhouse pocket, in pocket order.
+    pub const POCKET: [Piece; 5] = [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ];
+
+    pub fn from_letter(c: char) -> Option<Piece> {
+        match c.to_ascii_lowercase() {
+            'p' => Some(Piece::Pawn),
+            'n' => Some(Piece::Knight),
+            'b' => Some(Piece::Bishop),
+            'r' => Some(Piece::Rook),
+            'q' => Some(Piece::Queen),
+            'k' => Some(Piece::King),
+            _ => None,
+        }
+    }
+
+    /// Lowercase FEN letter.
+    pub fn letter(self) -> char {
+        b" pnbrqk"[self as usize] as char
+    }
+
+    /// Name used by the web UI and its piece images.
+    pub fn name(self) -> &'static str {
+        ["", "pawn", "knight", "bishop", "rook", "queen", "king"][self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Piece> {
+        Piece::ALL.into_iter().find(|piece| piece.name() == name)
+    }
+}
+
+/// Decodes a packed `Piece | Color` byte, `None` for `EMPTY`.
+pub fn unpack(code: u8) -> Option<(Piece, Color)> {
+    let piece = *Piece::ALL.get(((code & 7) as usize).checked_sub(1)?)?;
+    let color = if code & Color::White as u8 != 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Some((piece, color))
+}
+
 impl BitOr for Piece {
     type Output = u8;
 
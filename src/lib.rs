@@ -1,17 +1,35 @@
-#![feature(test)]
-#![feature(unbounded_shifts)]
-#![feature(portable_simd)]
+#![cfg_attr(feature = "nightly", feature(portable_simd))]
 
-extern crate test;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-mod bitboards;
-mod consts;
-mod gamestate;
-mod letterbox;
-mod moves;
-mod rays;
+pub mod bitboards;
+pub mod castling;
+pub mod clock;
+pub mod consts;
+pub mod epd;
+pub mod eval;
+pub mod gamestate;
+pub mod letterbox;
+pub mod moves;
+pub mod nnue;
+pub mod notation;
+pub mod polyglot;
+pub mod puzzle;
+pub mod rays;
+pub mod search;
+pub mod selfplay;
+pub mod solver;
+pub mod sprt;
+#[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+pub mod syzygy;
+pub mod timeman;
+pub mod tree;
+pub mod tt;
+pub mod tune;
+pub mod variant;
 
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
 unsafe extern "C" {
     #[wasm_bindgen(js_namespace = console)]
@@ -26,17 +44,8 @@ pub fn showasm(i: &mut i32) {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use test::Bencher;
-
-    #[bench]
-    fn legal_moves(b: &mut Bencher) {
-        let mut board =
-            gamestate::Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-        b.iter(|| board.calc_legal_moves())
-    }
-
     #[test]
+    #[should_panic(expected = "aaaaah!")]
     fn it_panics() {
         let x = "asdf";
         panic!("aaaaah! {x}");
@@ -1,20 +1,22 @@
 use crate::bitboards::*;
+use crate::castling::*;
 use crate::consts::*;
+use crate::letterbox::Color;
 // use crate::pieces::*;
 use crate::rays::*;
 
 pub fn gen_all_moves(
-    color: bool,
+    color: Color,
     bitboards: &BitBoards,
     enpassant: Option<usize>,
-    castling_rights_mask: u64,
+    castling_rights: u64,
 ) -> [u64; 64] {
     let friends: u64;
     let foes: u64;
     let consts: &Consts;
     let pawn_moves: fn(u64, u64, u64, &mut [u64]);
     let foes_pawn_attacks: &[u64; 64];
-    if color {
+    if color == Color::White {
         friends = bitboards.whites;
         foes = bitboards.blacks;
         consts = &WHITE_CONSTS;
@@ -28,6 +30,12 @@ pub fn gen_all_moves(
         foes_pawn_attacks = &WHITE_PAWN_ATTACKS;
     };
 
+    let king_bb = friends & bitboards.kings;
+    if king_bb == 0 {
+        // kingless armies (Horde) have nothing to keep safe
+        return gen_pseudo_moves(color, bitboards, enpassant);
+    }
+
     let mut moves = [0; 64];
     let free_squares = !(friends ^ foes);
     let occupancy = !free_squares;
@@ -55,18 +63,18 @@ pub fn gen_all_moves(
     knight_attacks(foes & bitboards.knights, 0, &mut foes_attacks);
 
     apply!(bitboards.pawns & foes, i -> foes_attacks[i] |= foes_pawn_attacks[i]);
-    let k_sq = bsf(bitboards.kings & foes) as usize;
-    foes_attacks[k_sq] |= KING_MOVES[k_sq];
+    apply!(bitboards.kings & foes, k -> foes_attacks[k] |= KING_MOVES[k]);
 
     let attacked = foes_attacks.iter().fold(0, |acc, e| acc | *e);
 
-    let king_bb = friends & bitboards.kings;
     let k_sq = bsf(king_bb) as usize;
 
     let not_friends_or_attacked = !(friends | attacked);
     moves[k_sq] |= KING_MOVES[k_sq] & not_friends_or_attacked;
 
     let mut check_mask = 0;
+    // the en passant square, when the pawn it captures is the checker
+    let mut enpassant_evasion = 0;
     let mut in_check = false;
     if attacked & king_bb != 0 {
         in_check = true;
@@ -84,8 +92,13 @@ pub fn gen_all_moves(
             return moves;
         };
 
-        // the checker can always be captured
+        // the checker can always be captured, a double-pushed pawn also en passant
         check_mask |= checker;
+        if let Some(ep_sq) = enpassant {
+            if checker == 1 << (ep_sq as i8 + 8 * consts.direction) {
+                enpassant_evasion = 1 << ep_sq;
+            }
+        }
 
         if checker & (bitboards.queens ^ bitboards.bishops) != 0 {
             check_mask |= DIAGONALS_INTERSECT[checker_square][k_sq];
@@ -93,19 +106,21 @@ pub fn gen_all_moves(
         if checker & (bitboards.queens ^ bitboards.rooks) != 0 {
             check_mask |= RANKFILES_INTERSECT[checker_square][k_sq];
         };
-    } else {
-        // castling masks don't include the king (poor choice?), so other checks are subordinated
-        // to "not in check" status.
-        if castling_rights_mask & consts.ks_castle & not_friends_or_attacked == consts.ks_castle
-            && friends & consts.ks_rook != 0
-        {
-            moves[k_sq] |= consts.ks_castle_k;
-        }
-        if castling_rights_mask & consts.qs_castle & not_friends_or_attacked == consts.qs_castle
-            && friends & consts.qs_rook != 0
-        {
-            moves[k_sq] |= consts.qs_castle_k;
-        }
+    } else if king_bb & consts.first_rank != 0 {
+        // `castling_rights` holds the squares of the rooks that may still castle.
+        // The castling rook leaves the back rank behind the king, so sliders it was
+        // shielding must not see the king's destination.
+        let rank_sliders = foes & (bitboards.queens ^ bitboards.rooks);
+        apply!(castling_rights & friends & bitboards.rooks & consts.first_rank, r -> {
+            let path = CastlingPath::new(k_sq, r);
+            let occ = occupancy ^ king_bb ^ (1 << r);
+            if path.empty & occ == 0
+                && path.safe & attacked == 0
+                && sliding_attacks::<{ Rays::RANKFILES }>(path.king_to, occ) & rank_sliders == 0
+            {
+                moves[k_sq] |= 1 << path.target();
+            }
+        });
     }
 
     pawn_moves(
@@ -143,35 +158,108 @@ pub fn gen_all_moves(
         k_sq,
         &mut moves,
     );
+    if let Some(ep_sq) = enpassant {
+        enpassant_pins(
+            ep_sq,
+            (ep_sq as i8 + 8 * consts.direction) as usize,
+            friends,
+            foes,
+            bitboards,
+            k_sq,
+            &mut moves,
+        );
+    }
     if in_check {
         // Check mask restraints pieces moves to interposing or capture.
         // King moves are not affected, and only depend on `attacked` squares
         let pieces = friends & !king_bb;
-        apply!(pieces, i -> moves[i] &= check_mask);
+        apply!(pieces & !bitboards.pawns, i -> moves[i] &= check_mask);
+        apply!(pieces & bitboards.pawns, i -> moves[i] &= check_mask | enpassant_evasion);
     }
     moves
 }
 
+/// Moves of every piece of `color`, ignoring the safety of its king. Castling is left out.
+pub fn gen_pseudo_moves(
+    color: Color,
+    bitboards: &BitBoards,
+    enpassant: Option<usize>,
+) -> [u64; 64] {
+    let friends: u64;
+    let foes: u64;
+    let pawn_moves: fn(u64, u64, u64, &mut [u64]);
+    if color == Color::White {
+        friends = bitboards.whites;
+        foes = bitboards.blacks;
+        pawn_moves = white_pawn_moves;
+    } else {
+        friends = bitboards.blacks;
+        foes = bitboards.whites;
+        pawn_moves = black_pawn_moves;
+    };
+    let occupancy = friends | foes;
+
+    let mut moves = [0; 64];
+    pawn_moves(
+        friends & bitboards.pawns,
+        foes ^ enpassant.map(|i| 1 << i).unwrap_or(0),
+        !occupancy,
+        &mut moves,
+    );
+    diagonal_attacks(
+        friends & (bitboards.queens ^ bitboards.bishops),
+        friends,
+        occupancy,
+        &mut moves,
+    );
+    rankfile_attacks(
+        friends & (bitboards.queens ^ bitboards.rooks),
+        friends,
+        occupancy,
+        &mut moves,
+    );
+    knight_attacks(friends & bitboards.knights, friends, &mut moves);
+    apply!(friends & bitboards.kings, i -> moves[i] |= KING_MOVES[i] & !friends);
+    moves
+}
+
+/// Pieces among `attackers` that attack `square`, with sliders blocked by `occupancy`.
+pub fn attackers_to(square: usize, attackers: u64, bitboards: &BitBoards, occupancy: u64) -> u64 {
+    let diagonals = bitboards.queens ^ bitboards.bishops;
+    let rankfiles = bitboards.queens ^ bitboards.rooks;
+    // a pawn attacks `square` from where an opposite pawn on `square` would attack
+    let pawns = BLACK_PAWN_ATTACKS[square] & bitboards.whites
+        | WHITE_PAWN_ATTACKS[square] & bitboards.blacks;
+    attackers
+        & (KNIGHT_MOVES[square] & bitboards.knights
+            | KING_MOVES[square] & bitboards.kings
+            | pawns & bitboards.pawns
+            | sliding_attacks::<{ Rays::DIAGONALS }>(square, occupancy) & diagonals
+            | sliding_attacks::<{ Rays::RANKFILES }>(square, occupancy) & rankfiles)
+}
+
 #[inline(always)]
 pub fn white_pawn_moves(pawns: u64, targets: u64, free_squares: u64, moves: &mut [u64]) {
-    let pawn_advances = {
-        let mut moves = 0;
-        moves |= pawns >> 8 & free_squares;
-        moves |= (moves & WHITE_CONSTS.third_rank) >> 8 & free_squares;
-        moves
-    };
-    apply!(pawns, i -> moves[i] |= (pawn_advances & WHITE_PAWN_ADVANCES[i]) ^ (WHITE_PAWN_ATTACKS[i] & targets));
+    let singles = pawns >> 8 & free_squares;
+    let doubles = (singles & WHITE_CONSTS.third_rank) >> 8 & free_squares;
+    // a pawn may only double push through its own single push: with doubled pawns the square
+    // two ahead of the rear pawn is the front pawn's single push.
+    apply!(pawns, i -> {
+        let single = singles & 1 << i >> 8;
+        let double = doubles & single >> 8;
+        moves[i] |= (single | double) ^ (WHITE_PAWN_ATTACKS[i] & targets)
+    });
 }
 
 #[inline(always)]
 pub fn black_pawn_moves(pawns: u64, targets: u64, free_squares: u64, moves: &mut [u64]) {
-    let pawn_advances = {
-        let mut moves = 0;
-        moves |= pawns << 8 & free_squares;
-        moves |= (moves & BLACK_CONSTS.third_rank) << 8 & free_squares;
-        moves
-    };
-    apply!(pawns, i -> moves[i] |= (pawn_advances & BLACK_PAWN_ADVANCES[i]) ^ (BLACK_PAWN_ATTACKS[i] & targets));
+    let singles = pawns << 8 & free_squares;
+    let doubles = (singles & BLACK_CONSTS.third_rank) << 8 & free_squares;
+    apply!(pawns, i -> {
+        let single = singles & 1 << i << 8;
+        let double = doubles & single << 8;
+        moves[i] |= (single | double) ^ (BLACK_PAWN_ATTACKS[i] & targets)
+    });
 }
 
 #[inline(always)]
@@ -211,12 +299,14 @@ fn rankfile_attacks(pieces: u64, friends: u64, occupancy: u64, moves: &mut [u64;
     );
 }
 
+/// A pinned piece may only move along the `xray` between king and pinner, or capture the
+/// `pinner` itself.
 #[inline(always)]
-fn pin_mask(xray: u64, friends: u64, foes: u64, moves: &mut [u64; 64]) {
+fn pin_mask(xray: u64, pinner: u64, friends: u64, foes: u64, moves: &mut [u64; 64]) {
     let maybe_pins = friends & xray;
     if xray & foes == 0 && maybe_pins.count_ones() == 1 {
         let idx = bsf(maybe_pins);
-        moves[idx as usize] &= xray;
+        moves[idx as usize] &= xray | pinner;
     }
 }
 
@@ -225,7 +315,7 @@ fn diagonal_pins(pieces: u64, friends: u64, foes: u64, k: usize, moves: &mut [u6
     apply!(
         pieces,
         i ->
-        pin_mask(DIAGONALS_INTERSECT[i][k], friends, foes, moves)
+        pin_mask(DIAGONALS_INTERSECT[i][k], 1 << i, friends, foes, moves)
     );
 }
 
@@ -234,17 +324,218 @@ fn rankfile_pins(pieces: u64, friends: u64, foes: u64, k: usize, moves: &mut [u6
     apply!(
         pieces,
         i ->
-        pin_mask(RANKFILES_INTERSECT[i][k], friends, foes, moves)
+        pin_mask(RANKFILES_INTERSECT[i][k], 1 << i, friends, foes, moves)
     );
 }
 
+/// En passant removes two pawns from the capture rank at once, which `pin_mask` cannot see:
+/// a king, the two pawns and an enemy slider sharing a rank is not a pin of either pawn.
+/// Replay the capture on the occupancy and drop it if the king ends up attacked.
+#[inline(always)]
+fn enpassant_pins(
+    ep_sq: usize,
+    captured_sq: usize,
+    friends: u64,
+    foes: u64,
+    bitboards: &BitBoards,
+    k_sq: usize,
+    moves: &mut [u64; 64],
+) {
+    let occupancy = friends | foes;
+    let ep_bb: u64 = 1 << ep_sq;
+    let captured_bb: u64 = 1 << captured_sq;
+    let diagonals = foes & (bitboards.queens ^ bitboards.bishops);
+    let rankfiles = foes & (bitboards.queens ^ bitboards.rooks) & !captured_bb;
+    apply!(friends & bitboards.pawns, i -> {
+        if moves[i] & ep_bb != 0 {
+            let occ = (occupancy ^ (1 << i) ^ captured_bb) | ep_bb;
+            if sliding_attacks::<{ Rays::DIAGONALS }>(k_sq, occ) & diagonals != 0
+                || sliding_attacks::<{ Rays::RANKFILES }>(k_sq, occ) & rankfiles != 0
+            {
+                moves[i] ^= ep_bb;
+            }
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use test::Bencher;
 
-    #[bench]
-    fn bench_shift(b: &mut Bencher) {
-        b.iter(|| shift::<N, ONES>(1 << 63));
+    fn sq(name: &str) -> usize {
+        let name = name.as_bytes();
+        (7 - (name[1] - b'1') as usize) * 8 + (name[0] - b'a') as usize
+    }
+
+    fn moves_from(position: &str, color: Color, enpassant: Option<&str>, from: &str) -> u64 {
+        let bitboards = BitBoards::from_fen(position);
+        gen_all_moves(color, &bitboards, enpassant.map(sq), 0)[sq(from)]
+    }
+
+    fn can_move(
+        position: &str,
+        color: Color,
+        enpassant: Option<&str>,
+        from: &str,
+        to: &str,
+    ) -> bool {
+        moves_from(position, color, enpassant, from) & 1 << sq(to) != 0
+    }
+
+    #[test]
+    fn enpassant_horizontal_pin() {
+        // king, both pawns and a slider on the same rank: capturing en passant exposes the king
+        assert!(!can_move(
+            "8/8/8/8/k2Pp2Q/8/8/3K4",
+            Color::Black,
+            Some("d3"),
+            "e4",
+            "d3"
+        ));
+        assert!(!can_move(
+            "8/8/8/8/k1pP3R/8/8/4K3",
+            Color::Black,
+            Some("d3"),
+            "c4",
+            "d3"
+        ));
+        assert!(!can_move(
+            "8/8/8/K1Pp3r/8/8/8/4k3",
+            Color::White,
+            Some("d6"),
+            "c5",
+            "d6"
+        ));
+        assert!(!can_move(
+            "8/8/8/r2pP2K/8/8/8/4k3",
+            Color::White,
+            Some("d6"),
+            "e5",
+            "d6"
+        ));
+        // the pawn may still push
+        assert!(can_move(
+            "8/8/8/8/k2Pp2Q/8/8/3K4",
+            Color::Black,
+            Some("d3"),
+            "e4",
+            "e3"
+        ));
+    }
+
+    #[test]
+    fn enpassant_horizontal_not_pinned() {
+        // another piece on the rank still shields the king
+        assert!(can_move(
+            "8/8/8/8/k2PpN1Q/8/8/3K4",
+            Color::Black,
+            Some("d3"),
+            "e4",
+            "d3"
+        ));
+        // the slider does not move along ranks
+        assert!(can_move(
+            "8/8/8/8/k2Pp2B/8/8/3K4",
+            Color::Black,
+            Some("d3"),
+            "e4",
+            "d3"
+        ));
+        // the king is not on the capture rank
+        assert!(can_move(
+            "8/8/8/k7/3Pp2Q/8/8/3K4",
+            Color::Black,
+            Some("d3"),
+            "e4",
+            "d3"
+        ));
+    }
+
+    #[test]
+    fn enpassant_diagonal_pin() {
+        // the capturing pawn is pinned: capturing off the pin line is illegal...
+        assert_eq!(
+            moves_from("7b/8/8/3pP3/8/8/8/K6k", Color::White, Some("d6"), "e5"),
+            0
+        );
+        // ...but capturing along it is fine
+        assert!(can_move(
+            "1b5k/8/8/3pP3/8/8/7K/8",
+            Color::White,
+            Some("d6"),
+            "e5",
+            "d6"
+        ));
+        assert!(can_move(
+            "8/7k/8/8/3Pp3/8/8/1B5K",
+            Color::Black,
+            Some("d3"),
+            "e4",
+            "d3"
+        ));
+    }
+
+    #[test]
+    fn enpassant_discovers_diagonal() {
+        // removing the captured pawn opens a diagonal onto the king
+        assert!(!can_move(
+            "8/8/1k6/8/3Pp3/8/5B2/7K",
+            Color::Black,
+            Some("d3"),
+            "e4",
+            "d3"
+        ));
+        // the capture itself may give check
+        assert!(can_move(
+            "8/8/1k6/2b5/2pP4/8/5K2/8",
+            Color::Black,
+            Some("d3"),
+            "c4",
+            "d3"
+        ));
+    }
+
+    #[test]
+    fn pinned_piece_captures_pinner() {
+        let moves = moves_from("4r2k/8/8/b7/8/8/3NR3/4K3", Color::White, None, "e2");
+        let file = RANKFILES_INTERSECT[sq("e8")][sq("e1")] ^ 1 << sq("e2");
+        assert_eq!(moves, file | 1 << sq("e8"));
+        assert_eq!(
+            moves_from("4r2k/8/8/b7/8/8/3NR3/4K3", Color::White, None, "d2"),
+            0
+        );
+    }
+
+    #[test]
+    fn kingless_side_moves_freely() {
+        // Horde: white has no king, so nothing is pinned
+        let moves = moves_from("4k3/4r3/8/8/8/8/4P3/8", Color::White, None, "e2");
+        assert_eq!(moves, 1 << sq("e3") | 1 << sq("e4"));
+        // and black's king is only kept away from white's pawns
+        let moves = moves_from("4k3/8/8/8/8/8/3P4/8", Color::Black, None, "e8");
+        assert_eq!(moves.count_ones(), 5);
+    }
+
+    #[test]
+    fn attackers() {
+        let bitboards = BitBoards::from_fen("4k3/8/8/3p4/4K3/8/8/4R3");
+        let occupancy = bitboards.whites | bitboards.blacks;
+        assert_eq!(
+            attackers_to(sq("e4"), bitboards.blacks, &bitboards, occupancy),
+            1 << sq("d5")
+        );
+        assert_eq!(
+            attackers_to(sq("e8"), bitboards.whites, &bitboards, occupancy),
+            0
+        );
+        assert_eq!(
+            attackers_to(
+                sq("e8"),
+                bitboards.whites,
+                &bitboards,
+                occupancy ^ 1 << sq("e4")
+            ),
+            1 << sq("e1")
+        );
     }
 }
@@ -0,0 +1,336 @@
+//! Efficiently updatable neural network evaluation.
+//!
+//! The network is `768 -> N x 2 -> 1`: every piece on the board is one of 768 features (its
+//! color relative to the side seen from, its kind and its square), summed into an accumulator
+//! of `N` values per side. The output layer reads the accumulator of the side to move, then
+//! the other, through a squared clipped ReLU.
+//!
+//! Network files start with a header of `MAGIC`, the format `VERSION` and `N`, the last two
+//! as little-endian `u32`s. Little-endian `i16`s follow, in order: the feature weights
+//! (`768 x N`, feature major), the feature biases (`N`), the output weights (`2 x N`) and the
+//! output bias, possibly zero padded to a multiple of 64 bytes. After the header, this is the
+//! layout of the simple networks that `bullet` trains, with its quantisation (`QA`, `QB`) and
+//! scale.
+
+use std::io;
+use std::path::Path;
+
+use crate::bitboards::*;
+use crate::gamestate::MoveResult;
+use crate::letterbox::{Color, Piece};
+
+/// First bytes of a network file.
+pub const MAGIC: &[u8; 4] = b"TNUE";
+/// Format of the networks this module reads.
+pub const VERSION: u32 = 1;
+const HEADER: usize = 12;
+
+const FEATURES: usize = 768;
+/// Quantisation of the accumulator, where the activation clips.
+const QA: i32 = 255;
+/// Quantisation of the output weights.
+const QB: i32 = 64;
+/// Centipawns per unit of network output.
+const SCALE: i32 = 400;
+
+/// Feature of `piece` of `color` on `square`, seen from `perspective`. Features count squares
+/// from a1, and black sees the board upside down.
+fn feature(perspective: Color, piece: Piece, color: Color, square: usize) -> usize {
+    let square = match perspective {
+        Color::White => square ^ 56,
+        Color::Black => square,
+    };
+    384 * (color != perspective) as usize + 64 * (piece as usize - 1) + square
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+/// First layer of the network for one position, indexed by the side it is seen from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Accumulator([Vec<i16>; 2]);
+
+impl Network {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Network::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Reads a network file, refusing files without the header or of the wrong size.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if bytes.len() < HEADER || &bytes[..4] != MAGIC {
+            return Err(invalid("not a network file".to_string()));
+        }
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let (version, hidden) = (word(4), word(8) as usize);
+        if version != VERSION {
+            return Err(invalid(format!("unsupported network version {version}")));
+        }
+        if hidden == 0 {
+            return Err(invalid("network without neurons".to_string()));
+        }
+        let size = 2 * ((FEATURES + 3) * hidden + 1);
+        let body = &bytes[HEADER..];
+        if body.len() != size && body.len() != size.next_multiple_of(64) {
+            return Err(invalid(format!(
+                "a network of {hidden} neurons takes {size} bytes, not {}",
+                body.len()
+            )));
+        }
+        let values: Vec<i16> = body[..size]
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        let (feature_weights, rest) = values.split_at(FEATURES * hidden);
+        let (feature_biases, rest) = rest.split_at(hidden);
+        let (output_weights, rest) = rest.split_at(2 * hidden);
+        Ok(Network {
+            hidden,
+            feature_weights: feature_weights.to_vec(),
+            feature_biases: feature_biases.to_vec(),
+            output_weights: output_weights.to_vec(),
+            output_bias: rest[0],
+        })
+    }
+
+    /// The network as a file, unpadded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((self.hidden as u32).to_le_bytes());
+        let values = self.feature_weights.iter().chain(&self.feature_biases);
+        let values = values
+            .chain(&self.output_weights)
+            .chain([&self.output_bias]);
+        bytes.extend(values.flat_map(|value| value.to_le_bytes()));
+        bytes
+    }
+
+    /// Size of the accumulator of each side.
+    pub fn hidden(&self) -> usize {
+        self.hidden
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    /// Adds a piece to both sides of `accumulator`, or takes it away when `sign` is negative.
+    fn toggle(
+        &self,
+        accumulator: &mut Accumulator,
+        piece: Piece,
+        color: Color,
+        square: usize,
+        sign: i16,
+    ) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = self.weights(feature(perspective, piece, color, square));
+            let values = &mut accumulator.0[perspective.index()];
+            for (value, &weight) in values.iter_mut().zip(weights) {
+                *value = value.wrapping_add(sign.wrapping_mul(weight));
+            }
+        }
+    }
+
+    /// The accumulator of `bitboards`, built from scratch.
+    pub fn refresh(&self, bitboards: &BitBoards) -> Accumulator {
+        let mut accumulator =
+            Accumulator([self.feature_biases.clone(), self.feature_biases.clone()]);
+        apply!(bitboards.whites | bitboards.blacks, square -> {
+            let (piece, color) = bitboards.piece_at(square).unwrap();
+            self.toggle(&mut accumulator, piece, color, square, 1);
+        });
+        accumulator
+    }
+
+    /// Brings `accumulator` from the position before a move of `mover` to the one after it,
+    /// `bitboards`. Moves that made the variant take pieces off the board, whose kinds the
+    /// result does not tell, rebuild the accumulator instead.
+    pub fn update(
+        &self,
+        accumulator: &mut Accumulator,
+        result: &MoveResult,
+        mover: Color,
+        bitboards: &BitBoards,
+    ) {
+        if !result.removed.is_empty() {
+            *accumulator = self.refresh(bitboards);
+            return;
+        }
+        if let Some((square, piece)) = result.captured {
+            self.toggle(accumulator, piece, !mover, square, -1);
+        }
+        if let Some(from) = result.from {
+            self.toggle(accumulator, result.piece, mover, from, -1);
+        }
+        let landed = result.promotion.unwrap_or(result.piece);
+        self.toggle(accumulator, landed, mover, result.to, 1);
+        if let Some((from, to)) = result.rook {
+            self.toggle(accumulator, Piece::Rook, mover, from, -1);
+            self.toggle(accumulator, Piece::Rook, mover, to, 1);
+        }
+    }
+
+    /// Score in centipawns for `side`.
+    pub fn evaluate(&self, accumulator: &Accumulator, side: Color) -> i32 {
+        let (ours, theirs) = self.output_weights.split_at(self.hidden);
+        let sum = screlu_dot(&accumulator.0[side.index()], ours)
+            + screlu_dot(&accumulator.0[(!side).index()], theirs);
+        (sum / QA + self.output_bias as i32) * SCALE / (QA * QB)
+    }
+}
+
+/// Sum of `clamp(value, 0, QA)² * weight`.
+#[cfg(not(feature = "nightly"))]
+fn screlu_dot(values: &[i16], weights: &[i16]) -> i32 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&value, &weight)| {
+            let value = (value as i32).clamp(0, QA);
+            value * value * weight as i32
+        })
+        .sum()
+}
+
+/// Sum of `clamp(value, 0, QA)² * weight`, sixteen lanes at a time.
+#[cfg(feature = "nightly")]
+fn screlu_dot(values: &[i16], weights: &[i16]) -> i32 {
+    use std::simd::prelude::*;
+
+    let (zero, qa) = (i32x16::splat(0), i32x16::splat(QA));
+    let mut sum = i32x16::splat(0);
+    let chunks = values.chunks_exact(16).zip(weights.chunks_exact(16));
+    for (value, weight) in chunks {
+        let value = i16x16::from_slice(value).cast::<i32>().simd_clamp(zero, qa);
+        sum += value * value * i16x16::from_slice(weight).cast::<i32>();
+    }
+    let tail = values.len() / 16 * 16;
+    let rest: i32 = values[tail..]
+        .iter()
+        .zip(&weights[tail..])
+        .map(|(&value, &weight)| {
+            let value = (value as i32).clamp(0, QA);
+            value * value * weight as i32
+        })
+        .sum();
+    sum.reduce_sum() + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamestate::Game;
+
+    /// A network of made up weights, small enough not to overflow.
+    fn network(hidden: usize) -> Network {
+        let mut seed = 0x2545F4914F6CDD1Du64;
+        let mut values = vec![];
+        for _ in 0..(FEATURES + 3) * hidden + 1 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            values.push((seed % 61) as i16 - 30);
+        }
+        Network::from_bytes(&file(hidden, &values)).unwrap()
+    }
+
+    fn file(hidden: usize, values: &[i16]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((hidden as u32).to_le_bytes());
+        bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+        bytes
+    }
+
+    /// Plays `moves` in UCI notation, checking the updated accumulator against a fresh one.
+    fn play(net: &Network, mut game: Game, moves: &[&str]) {
+        let mut accumulator = net.refresh(&game.bitboards);
+        for uci in moves {
+            let mover = game.side_to_move();
+            let result = match game.parse_uci_drop(uci) {
+                Some((piece, to)) => game.input_drop(piece, to),
+                None => {
+                    let (from, to, promotion) = game.parse_uci_move(uci).unwrap();
+                    game.input_move(from, to, promotion)
+                }
+            }
+            .unwrap_or_else(|| panic!("{uci} is not legal"));
+            net.update(&mut accumulator, &result, mover, &game.bitboards);
+            assert_eq!(accumulator, net.refresh(&game.bitboards), "after {uci}");
+        }
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let net = network(20);
+        assert_eq!(net.hidden(), 20);
+        // captures, castling, en passant and an underpromotion
+        play(
+            &net,
+            Game::from_fen("r3k2r/1P6/8/8/5p2/8/4P3/R3K2R w KQkq - 0 1"),
+            &["e2e4", "f4e3", "b7a8n", "e8g8", "e1c1", "f8f1", "d1f1"],
+        );
+        play(
+            &net,
+            Game::new(
+                "4k3/8/8/8/8/8/8/4K3[Nq] w - - 0 1",
+                Some("crazyhouse".into()),
+            )
+            .unwrap(),
+            &["N@d6", "e8d7", "d6f7"],
+        );
+        play(
+            &net,
+            Game::new(
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                Some("atomic".into()),
+            )
+            .unwrap(),
+            &["g1f3", "d7d5", "f3e5", "d8d6", "e5f7"],
+        );
+    }
+
+    #[test]
+    fn mirrored_positions_score_alike() {
+        let net = network(32);
+        let white = Game::from_fen("4k3/8/8/8/4P3/2N5/8/4K3 w - - 0 1");
+        let black = Game::from_fen("4k3/8/2n5/4p3/8/8/8/4K3 b - - 0 1");
+        assert_eq!(
+            net.evaluate(&net.refresh(&white.bitboards), Color::White),
+            net.evaluate(&net.refresh(&black.bitboards), Color::Black)
+        );
+    }
+
+    #[test]
+    fn files() {
+        let net = network(4);
+        assert_eq!(Network::from_bytes(&net.to_bytes()).unwrap(), net);
+        let values = vec![1; 771 * 4 + 1];
+        assert_eq!(Network::from_bytes(&file(4, &values)).unwrap().hidden(), 4);
+        let mut padded = file(4, &values);
+        padded.resize(HEADER + (2 * values.len()).next_multiple_of(64), 0);
+        assert!(Network::from_bytes(&padded).is_ok());
+
+        // headerless, of another version, or too short or long for their size
+        let error = |bytes: &[u8]| Network::from_bytes(bytes).unwrap_err().to_string();
+        assert_eq!(error(&[0; 10000]), "not a network file");
+        let mut other = file(4, &values);
+        other[4] = 2;
+        assert_eq!(error(&other), "unsupported network version 2");
+        assert_eq!(error(&file(0, &[])), "network without neurons");
+        assert_eq!(
+            error(&file(4, &values[1..])),
+            "a network of 4 neurons takes 6170 bytes, not 6168"
+        );
+        padded.push(0);
+        assert!(Network::from_bytes(&padded).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+/// Algebraic name of a board index: 0 is a8, 63 is h1.
+pub fn square_name(square: usize) -> String {
+    let file = (b'a' + (square % 8) as u8) as char;
+    let rank = (b'8' - (square / 8) as u8) as char;
+    format!("{file}{rank}")
+}
+
+/// Board index of an algebraic square name such as `e4`.
+pub fn parse_square(name: &str) -> Option<usize> {
+    match name.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+            Some((b'8' - rank) as usize * 8 + (file - b'a') as usize)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_names() {
+        assert_eq!(square_name(0), "a8");
+        assert_eq!(square_name(60), "e1");
+        assert_eq!(parse_square("h1"), Some(63));
+        assert_eq!(parse_square("d6"), Some(19));
+        assert_eq!(parse_square("i1"), None);
+        for square in 0..64 {
+            assert_eq!(parse_square(&square_name(square)), Some(square));
+        }
+    }
+}
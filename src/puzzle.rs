@@ -0,0 +1,299 @@
+//! Tactics puzzles in the Lichess CSV format, and a session that plays one: the opponent's
+//! moves are made for the solver, whose moves are checked against the solution.
+
+#[cfg(feature = "wasm")]
+use js_sys::Int32Array;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use std::io;
+use std::path::Path;
+
+use crate::gamestate::{Game, MoveResult};
+use crate::letterbox::{Color, Piece};
+use crate::variant::Outcome;
+
+/// One row of the Lichess puzzle database: `PuzzleId,FEN,Moves,Rating,RatingDeviation,
+/// Popularity,NbPlays,Themes,GameUrl,OpeningTags`. The FEN is the position before the
+/// opponent's move that sets the puzzle, which is the first of `moves`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    /// UCI moves, the opponent's first, then the solver's and the opponent's in turn.
+    pub moves: Vec<String>,
+    pub rating: Option<u32>,
+    pub themes: Vec<String>,
+}
+
+impl Puzzle {
+    /// Parses a CSV row, nothing for the header or rows without a FEN and moves.
+    pub fn parse(line: &str) -> Option<Puzzle> {
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields.len() < 3 || fields[0] == "PuzzleId" {
+            return None;
+        }
+        let moves: Vec<String> = fields[2].split_whitespace().map(str::to_string).collect();
+        if fields[1].is_empty() || moves.len() < 2 {
+            return None;
+        }
+        Some(Puzzle {
+            id: fields[0].to_string(),
+            fen: fields[1].to_string(),
+            moves,
+            rating: fields.get(3).and_then(|r| r.parse().ok()),
+            themes: fields.get(7).map_or(vec![], |t| {
+                t.split_whitespace().map(str::to_string).collect()
+            }),
+        })
+    }
+
+    /// Every puzzle of a CSV file, the header and malformed rows skipped.
+    pub fn load(text: &str) -> Vec<Puzzle> {
+        text.lines().filter_map(Puzzle::parse).collect()
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Vec<Puzzle>> {
+        Ok(Puzzle::load(&std::fs::read_to_string(path)?))
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Playing,
+    Solved,
+    /// A wrong move was tried, or the solution goes on with a move that cannot be played.
+    Failed,
+}
+
+/// A puzzle being solved. Wrong moves are not played: they fail the puzzle and leave the
+/// board as it was.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone)]
+pub struct PuzzleSession {
+    puzzle: Puzzle,
+    game: Game,
+    /// The side the solver plays.
+    player: Color,
+    /// Moves of the solution played so far.
+    played: usize,
+    status: Status,
+    /// The opponent's answer to the last move, until the UI takes it.
+    reply: Option<MoveResult>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl PuzzleSession {
+    /// Starts the puzzle of a CSV row, nothing if the row or its FEN does not parse or its
+    /// first move is illegal.
+    pub fn from_csv(line: &str) -> Option<PuzzleSession> {
+        PuzzleSession::new(Puzzle::parse(line)?)
+    }
+
+    pub fn id(&self) -> String {
+        self.puzzle.id.clone()
+    }
+
+    pub fn rating(&self) -> Option<u32> {
+        self.puzzle.rating
+    }
+
+    /// The side the solver plays.
+    pub fn player(&self) -> Color {
+        self.player
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Position on the board.
+    pub fn game(&self) -> Game {
+        self.game.clone()
+    }
+
+    /// Plays the solver's move, and the opponent's reply after it, which `take_reply` hands
+    /// over. A move off the solution fails the puzzle and is not played, unless it mates.
+    /// Nothing once the puzzle is over or if the move is illegal or wrong.
+    pub fn input_move(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<Piece>,
+    ) -> Option<MoveResult> {
+        if self.status != Status::Playing {
+            return None;
+        }
+        let mut game = self.game.clone();
+        let result = game.input_move(from, to, promotion)?;
+        let expected = self.game.parse_uci_move(&self.puzzle.moves[self.played]);
+        let mates = game.outcome() == Some(Outcome::Winner(self.player));
+        if expected != Some((from, to, promotion)) && !mates {
+            self.status = Status::Failed;
+            return None;
+        }
+        self.game = game;
+        self.played += 1;
+        if mates || self.played == self.puzzle.moves.len() {
+            self.status = Status::Solved;
+        } else {
+            self.reply = self.play_opponent();
+            if self.reply.is_none() {
+                self.status = Status::Failed;
+            }
+            // a solution that stops on the opponent's move is over with it
+            if self.played == self.puzzle.moves.len() {
+                self.status = Status::Solved;
+            }
+        }
+        Some(result)
+    }
+
+    /// The opponent's answer to the last move, once.
+    pub fn take_reply(&mut self) -> Option<MoveResult> {
+        self.reply.take()
+    }
+
+    /// The next move of the solution in UCI notation, nothing once the puzzle is over.
+    pub fn hint(&self) -> Option<String> {
+        (self.status == Status::Playing).then(|| self.puzzle.moves[self.played].clone())
+    }
+
+    /// Delegates to the position, so the board UI can show a puzzle like a game.
+    pub fn send_board(&self) -> String {
+        self.game.send_board()
+    }
+
+    pub fn variant(&self) -> String {
+        self.game.variant()
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.game.to_fen()
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl PuzzleSession {
+    pub fn get_legal_moves(&self) -> Vec<Int32Array> {
+        self.game.clone().get_legal_moves()
+    }
+}
+
+impl PuzzleSession {
+    /// Sets up `puzzle` and plays the opponent's first move, nothing if the FEN is malformed
+    /// or the move illegal.
+    pub fn new(puzzle: Puzzle) -> Option<PuzzleSession> {
+        let game = Game::try_from_fen(&puzzle.fen)?;
+        let mut session = PuzzleSession {
+            player: !game.side_to_move(),
+            game,
+            puzzle,
+            played: 0,
+            status: Status::Playing,
+            reply: None,
+        };
+        session.play_opponent()?;
+        if session.played == session.puzzle.moves.len() {
+            session.status = Status::Solved;
+        }
+        Some(session)
+    }
+
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.puzzle
+    }
+
+    fn play_opponent(&mut self) -> Option<MoveResult> {
+        let uci = self.puzzle.moves.get(self.played)?;
+        let (from, to, promotion) = self.game.parse_uci_move(uci)?;
+        let result = self.game.input_move(from, to, promotion)?;
+        self.played += 1;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,\
+GameUrl,OpeningTags
+00008,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,\
+1913,75,94,6353,crushing hangingPiece long middlegame,https://lichess.org/787zsVup/black#48,
+mate1,7k/5ppp/8/8/8/8/5PPP/R3R1K1 b - - 0 1,h8g8 a1a8,600,80,90,100,mate mateIn1 short,,
+broken,,e2e4 e7e5,1000,,,,,,
+illegal,7k/5ppp/8/8/8/8/5PPP/R3R1K1 b - - 0 1,h8g8 g1f1 e2e4 a1a8,600,,,,,,
+odd,7k/5ppp/8/8/8/8/5PPP/R3R1K1 b - - 0 1,h8g8 g1f1 g8h8,600,,,,,,
+nofen,not a fen,e2e4 e7e5,600,,,,,,
+";
+
+    /// Plays a UCI move in the session.
+    fn play(session: &mut PuzzleSession, uci: &str) -> Option<MoveResult> {
+        let (from, to, promotion) = session.game.parse_uci_move(uci).unwrap();
+        session.input_move(from, to, promotion)
+    }
+
+    #[test]
+    fn load() {
+        let puzzles = Puzzle::load(CSV);
+        assert_eq!(puzzles.len(), 5);
+        assert_eq!(puzzles[0].id, "00008");
+        assert_eq!(puzzles[0].moves.len(), 6);
+        assert_eq!(puzzles[0].rating, Some(1913));
+        assert_eq!(puzzles[1].themes, ["mate", "mateIn1", "short"]);
+    }
+
+    #[test]
+    fn solve() {
+        let mut session = PuzzleSession::from_csv(CSV.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(session.player(), Color::White);
+        assert_eq!(session.hint().as_deref(), Some("e6e7"));
+        play(&mut session, "e6e7").unwrap();
+        assert_eq!(session.take_reply().unwrap().san, "Qb1+");
+        assert!(session.take_reply().is_none());
+        play(&mut session, "b3c1").unwrap();
+        assert_eq!(session.take_reply().unwrap().san, "Qxc1+");
+        assert_eq!(session.status(), Status::Playing);
+        play(&mut session, "h6c1").unwrap();
+        assert_eq!(session.status(), Status::Solved);
+        assert_eq!(session.hint(), None);
+        assert!(play(&mut session, "c1c2").is_none());
+    }
+
+    #[test]
+    fn mistakes_and_other_mates() {
+        let line = CSV.lines().nth(2).unwrap();
+        let mut session = PuzzleSession::from_csv(line).unwrap();
+        let fen = session.to_fen();
+        assert!(play(&mut session, "g1f1").is_none());
+        assert_eq!(session.status(), Status::Failed);
+        assert_eq!(session.to_fen(), fen);
+
+        // another mate than the one in the solution solves it too
+        let mut session = PuzzleSession::from_csv(line).unwrap();
+        assert_eq!(play(&mut session, "e1e8").unwrap().san, "Re8#");
+        assert_eq!(session.status(), Status::Solved);
+
+        assert!(PuzzleSession::from_csv(CSV.lines().nth(3).unwrap()).is_none());
+        assert!(PuzzleSession::from_csv(CSV.lines().nth(6).unwrap()).is_none());
+
+        // a reply that cannot be played ends the puzzle
+        let mut session = PuzzleSession::from_csv(CSV.lines().nth(4).unwrap()).unwrap();
+        assert!(play(&mut session, "g1f1").is_some());
+        assert!(session.take_reply().is_none());
+        assert_eq!(session.status(), Status::Failed);
+        assert_eq!(session.hint(), None);
+
+        // a solution that ends on the opponent's move is solved with it
+        let mut session = PuzzleSession::from_csv(CSV.lines().nth(5).unwrap()).unwrap();
+        assert_eq!(session.hint().as_deref(), Some("g1f1"));
+        play(&mut session, "g1f1").unwrap();
+        assert_eq!(session.take_reply().unwrap().san, "Kh8");
+        assert_eq!(session.status(), Status::Solved);
+        assert_eq!(session.hint(), None);
+        assert!(play(&mut session, "a1a8").is_none());
+    }
+}
@@ -79,7 +79,7 @@ pub static RANKFILES_INTERSECT: [[u64; 64]; 64] = {
     while i < 64 {
         let mut j = 0;
         while j < i {
-            intersections[i][j] = ray_intersect::<{ Rays::DIAGONALS }>(i, j);
+            intersections[i][j] = ray_intersect::<{ Rays::RANKFILES }>(i, j);
             intersections[j][i] = intersections[i][j];
             j += 1;
         }
@@ -94,7 +94,7 @@ pub static DIAGONALS_INTERSECT: [[u64; 64]; 64] = {
     while i < 64 {
         let mut j = 0;
         while j < i {
-            intersections[i][j] = ray_intersect::<{ Rays::RANKFILES }>(i, j);
+            intersections[i][j] = ray_intersect::<{ Rays::DIAGONALS }>(i, j);
             intersections[j][i] = intersections[i][j];
             j += 1;
         }
@@ -129,7 +129,7 @@ const fn ray_intersect<const R: usize>(sq1: usize, sq2: usize) -> u64 {
 
 #[inline]
 /// Repeatedly shifts `board` bitboard by `D` bits, checking for `B` boundaries before shifting:
-/// ```
+/// ```ignore
 ///    if D >= 0 {
 ///        (board & B) << D // unbounded
 ///    } else {
@@ -0,0 +1,914 @@
+//! Iteratively deepened alpha-beta search with a capture-only quiescence search, on one or
+//! more threads sharing a transposition table.
+
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use serde::Serialize;
+
+use crate::eval::*;
+use crate::gamestate::{Game, Move};
+use crate::letterbox::Piece;
+use crate::nnue::{Accumulator, Network};
+#[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+use crate::syzygy::{Tablebases, Wdl};
+use crate::timeman::{TimeLeft, TimeManager};
+use crate::tt::{position_key, Bound, TranspositionTable};
+use crate::variant::Outcome;
+
+/// Score of a mate on the board; mates further away score `MATE - plies`.
+pub const MATE: i32 = 30_000;
+const INFINITY: i32 = MATE + 1;
+const MAX_DEPTH: u32 = 64;
+/// Score of a position the tablebases call won, below every mate score; wins further away
+/// score `TB_WIN - plies`.
+#[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+const TB_WIN: i32 = MATE - 2 * MAX_DEPTH as i32;
+
+/// When to stop searching. Without any limit the search stops at `MAX_DEPTH`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+    /// Clocks to budget the move from, see `TimeManager`.
+    pub time: Option<TimeLeft>,
+}
+
+impl Limits {
+    /// Reads the arguments of a UCI `go` command, as in `go wtime 60000 btime 58000 winc 1000`.
+    /// Unknown arguments, `infinite` among them, are skipped.
+    pub fn parse_go(command: &str) -> Limits {
+        let mut limits = Limits::default();
+        let mut time = TimeLeft::default();
+        let mut clocked = false;
+        let mut words = command.split_whitespace();
+        while let Some(word) = words.next() {
+            let Some(value) = words.clone().next().and_then(|v| v.parse::<u64>().ok()) else {
+                continue;
+            };
+            let millis = Duration::from_millis(value);
+            match word {
+                "depth" => limits.depth = Some(value as u32),
+                "movetime" => limits.movetime = Some(millis),
+                "wtime" => time.wtime = millis,
+                "btime" => time.btime = millis,
+                "winc" => time.winc = millis,
+                "binc" => time.binc = millis,
+                "movestogo" => time.movestogo = Some(value as u32),
+                _ => continue,
+            }
+            clocked |= matches!(word, "wtime" | "btime");
+            words.next();
+        }
+        if clocked {
+            limits.time = Some(time);
+        }
+        limits
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    /// Centipawns for the side to move, or `MATE - plies` when a mate was found.
+    pub score: i32,
+    /// Last fully searched depth.
+    pub depth: u32,
+    pub nodes: u64,
+    /// The best lines, as many as the `MultiPV` option asks for and there are moves, the
+    /// best first.
+    pub lines: Vec<Line>,
+}
+
+impl SearchResult {
+    /// The lines as UCI `info` lines, the best first:
+    /// `info depth 6 multipv 1 score cp 35 nodes 4242 pv e2e4 e7e5 g1f3`.
+    pub fn uci_info(&self, game: &Game) -> Vec<String> {
+        self.lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| {
+                let score = match mate_in(line.score) {
+                    Some(moves) => format!("mate {moves}"),
+                    None => format!("cp {}", line.score),
+                };
+                let mut game = game.clone();
+                let mut pv = String::new();
+                for &(from, to, promotion) in &line.moves {
+                    pv += &format!(" {}", game.uci_move(from, to, promotion));
+                    game.make_move(from, to, promotion);
+                }
+                format!(
+                    "info depth {} multipv {} score {score} nodes {} pv{pv}",
+                    line.depth,
+                    index + 1,
+                    self.nodes
+                )
+            })
+            .collect()
+    }
+}
+
+/// Moves to the mate `score` announces, negative when the side to move is the one mated.
+fn mate_in(score: i32) -> Option<i32> {
+    match score {
+        s if s >= MATE - MAX_DEPTH as i32 => Some((MATE - s + 1) / 2),
+        s if s <= MAX_DEPTH as i32 - MATE => Some(-(MATE + s) / 2),
+        _ => None,
+    }
+}
+
+/// A principal variation of the search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The root move first, then the expected replies as far as the hash table knows them.
+    pub moves: Vec<Move>,
+    /// As `SearchResult::score`.
+    pub score: i32,
+    pub depth: u32,
+}
+
+/// Most valuable victim first, then least valuable attacker, then promotions.
+fn order(game: &Game, moves: &mut [Move]) {
+    let foes = game.bitboards.color(!game.turn);
+    moves.sort_by_key(|&(from, to, promotion)| {
+        let victim = match game
+            .bitboards
+            .piece_at(to)
+            .filter(|_| foes & (1 << to) != 0)
+        {
+            Some((piece, _)) => 10 * PIECE_VALUES[piece as usize] + 1000,
+            None => 0,
+        };
+        let attacker = game.bitboards.piece_at(from).map_or(0, |(p, _)| p as i32);
+        let promotion = if promotion == Some(Piece::Queen) {
+            900
+        } else {
+            0
+        };
+        -(victim - attacker + promotion)
+    });
+}
+
+fn is_capture(game: &Game, (from, to, promotion): Move) -> bool {
+    let foes = game.bitboards.color(!game.turn);
+    foes & (1 << to) != 0
+        || promotion == Some(Piece::Queen)
+        || game.enpassant == Some(to) && game.bitboards.pawns & (1 << from) != 0
+}
+
+/// Moves `m`, if it is in `moves`, to `index`, shifting the moves in between down by one.
+fn bring_forward(moves: &mut [Move], m: Move, index: usize) {
+    if let Some(at) = moves.iter().position(|&other| other == m) {
+        if at > index {
+            moves[index..=at].rotate_right(1);
+        }
+    }
+}
+
+/// One search thread. Threads share only the transposition table and the stop flag.
+struct Searcher<'a> {
+    tt: &'a TranspositionTable,
+    stop: &'a AtomicBool,
+    network: Option<&'a Network>,
+    #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+    tablebases: Option<&'a Tablebases>,
+    nodes: u64,
+    deadline: Option<Instant>,
+    stopped: bool,
+    /// Last quiet move that caused a cutoff, per ply.
+    killers: [Option<Move>; MAX_DEPTH as usize],
+}
+
+impl<'a> Searcher<'a> {
+    fn new(engine: &'a Engine, stop: &'a AtomicBool) -> Self {
+        Searcher {
+            tt: &engine.tt,
+            stop,
+            network: engine.network.as_ref(),
+            #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+            tablebases: engine.tablebases.as_ref(),
+            nodes: 0,
+            deadline: None,
+            stopped: false,
+            killers: [None; MAX_DEPTH as usize],
+        }
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        if self.nodes & 1023 == 0 {
+            self.stopped |= self.stop.load(Ordering::Relaxed)
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.stopped
+    }
+
+    /// Score of a finished game, or `None` if it goes on.
+    fn terminal(game: &Game, ply: u32) -> Option<i32> {
+        match game.outcome {
+            Some(Outcome::Winner(color)) if color == game.turn => Some(MATE - ply as i32),
+            Some(Outcome::Winner(_)) => Some(ply as i32 - MATE),
+            Some(Outcome::Draw) => Some(0),
+            None if game.halfmove >= 100 => Some(0),
+            None => None,
+        }
+    }
+
+    /// Network evaluation when a network is loaded, the handcrafted one otherwise.
+    fn evaluate(&self, game: &Game, accumulator: Option<&Accumulator>) -> i32 {
+        match self.network.zip(accumulator) {
+            Some((network, accumulator)) => network.evaluate(accumulator, game.turn),
+            None => evaluate(game),
+        }
+    }
+
+    /// `game` after a legal move, with its accumulator brought up to date.
+    fn play(
+        &self,
+        game: &Game,
+        accumulator: Option<&Accumulator>,
+        (from, to, promotion): Move,
+    ) -> (Game, Option<Accumulator>) {
+        let mut child = game.clone();
+        let result = child.make_move(from, to, promotion).unwrap();
+        let accumulator = self.network.zip(accumulator).map(|(network, accumulator)| {
+            let mut accumulator = accumulator.clone();
+            network.update(&mut accumulator, &result, game.turn, &child.bitboards);
+            accumulator
+        });
+        (child, accumulator)
+    }
+
+    /// The tablebase result of a position right after a capture or pawn move, as a score and
+    /// the bound it is: cursed wins and blessed losses are draws under the fifty-move rule,
+    /// scored just off zero.
+    #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+    fn probe_tablebases(&self, game: &Game, ply: u32) -> Option<(i32, Bound)> {
+        let tablebases = self.tablebases?;
+        if game.halfmove != 0 || !tablebases.covers(game) {
+            return None;
+        }
+        Some(match tablebases.probe_wdl(game)? {
+            Wdl::Win => (TB_WIN - ply as i32, Bound::Lower),
+            Wdl::Loss => (ply as i32 - TB_WIN, Bound::Upper),
+            wdl => (2 * wdl as i32, Bound::Exact),
+        })
+    }
+
+    #[cfg(not(all(feature = "syzygy", not(target_arch = "wasm32"))))]
+    fn probe_tablebases(&self, _: &Game, _: u32) -> Option<(i32, Bound)> {
+        None
+    }
+
+    fn negamax(
+        &mut self,
+        game: &Game,
+        accumulator: Option<&Accumulator>,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        ply: u32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+        if let Some(score) = Self::terminal(game, ply) {
+            return score;
+        }
+        if depth == 0 {
+            return self.quiesce(game, accumulator, alpha, beta, ply);
+        }
+
+        let key = position_key(game);
+        let entry = self.tt.probe(key);
+        if let Some(entry) = entry.filter(|entry| entry.depth >= depth) {
+            let score = entry.score(ply);
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                return score;
+            }
+        }
+        if let Some((score, bound)) = self.probe_tablebases(game, ply) {
+            let cutoff = match bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                self.tt.store(key, ply, depth + 6, score, bound, None);
+                return score;
+            }
+        }
+
+        let mut moves = game.moves();
+        if moves.is_empty() {
+            return self.evaluate(game, accumulator);
+        }
+        order(game, &mut moves);
+        let quiet = moves
+            .iter()
+            .position(|&m| !is_capture(game, m))
+            .unwrap_or(moves.len());
+        if let Some(killer) = self.killers.get(ply as usize).copied().flatten() {
+            bring_forward(&mut moves, killer, quiet);
+        }
+        if let Some(hash_move) = entry.and_then(|entry| entry.best_move) {
+            bring_forward(&mut moves, hash_move, 0);
+        }
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for m in moves {
+            let (child, child_accumulator) = self.play(game, accumulator, m);
+            let score = -self.negamax(
+                &child,
+                child_accumulator.as_ref(),
+                depth - 1,
+                -beta,
+                -alpha,
+                ply + 1,
+            );
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(m);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                if !is_capture(game, m) {
+                    if let Some(killer) = self.killers.get_mut(ply as usize) {
+                        *killer = Some(m);
+                    }
+                }
+                break;
+            }
+        }
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(key, ply, depth, best, bound, best_move);
+        best
+    }
+
+    fn quiesce(
+        &mut self,
+        game: &Game,
+        accumulator: Option<&Accumulator>,
+        mut alpha: i32,
+        beta: i32,
+        ply: u32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+        if let Some(score) = Self::terminal(game, ply) {
+            return score;
+        }
+        let stand_pat = self.evaluate(game, accumulator);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = game.moves();
+        moves.retain(|&m| is_capture(game, m));
+        order(game, &mut moves);
+        for m in moves {
+            let (child, child_accumulator) = self.play(game, accumulator, m);
+            let score = -self.quiesce(&child, child_accumulator.as_ref(), -beta, -alpha, ply + 1);
+            if self.stopped {
+                return 0;
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        alpha
+    }
+
+    /// Iterative deepening over the root `moves`, from `first_depth` to `max_depth`, finding
+    /// the best `multipv` of them at each depth: every line after the first searches the
+    /// moves the earlier ones left. The deadline only applies from the second depth searched
+    /// on. Returns the best moves with their scores, and the depth they were found at.
+    fn iterate(
+        &mut self,
+        game: &Game,
+        mut moves: Vec<Move>,
+        depths: RangeInclusive<u32>,
+        multipv: usize,
+        deadline: Option<Instant>,
+        mut timer: Option<TimeManager>,
+    ) -> (Vec<(Move, i32)>, u32) {
+        let mut result = (vec![], 0);
+        let accumulator = self.network.map(|network| network.refresh(&game.bitboards));
+        for depth in depths {
+            let mut found: Vec<(Move, i32)> = vec![];
+            while found.len() < multipv.min(moves.len()) {
+                let mut alpha = -INFINITY;
+                let mut best_move = None;
+                for &m in &moves[found.len()..] {
+                    let (child, child_accumulator) = self.play(game, accumulator.as_ref(), m);
+                    let score = -self.negamax(
+                        &child,
+                        child_accumulator.as_ref(),
+                        depth - 1,
+                        -INFINITY,
+                        -alpha,
+                        1,
+                    );
+                    if self.stopped {
+                        break;
+                    }
+                    if score > alpha {
+                        alpha = score;
+                        best_move = Some(m);
+                    }
+                }
+                let Some(best) = best_move.filter(|_| !self.stopped) else {
+                    break;
+                };
+                // moves found so far lead the list, so that the next line skips them
+                bring_forward(&mut moves, best, found.len());
+                found.push((best, alpha));
+            }
+            if self.stopped {
+                break;
+            }
+
+            let (best, score) = found[0];
+            result = (found, depth);
+            if score.abs() >= MATE - depth as i32 {
+                break;
+            }
+            if let Some(timer) = &mut timer {
+                if !timer.keep_going(best, score) {
+                    break;
+                }
+            }
+            self.deadline = deadline;
+        }
+        result
+    }
+}
+
+/// Hash table size of a new `Engine`, in megabytes.
+const DEFAULT_HASH: usize = 16;
+
+/// Search state kept from one search to the next, set up through UCI-style options.
+pub struct Engine {
+    tt: TranspositionTable,
+    threads: usize,
+    multipv: usize,
+    /// Evaluates positions in place of `eval::evaluate` when loaded.
+    network: Option<Network>,
+    /// Restrict the root moves and end the search early in the endgames they cover.
+    #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+    tablebases: Option<Tablebases>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            tt: TranspositionTable::new(DEFAULT_HASH),
+            threads: 1,
+            multipv: 1,
+            network: None,
+            #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+            tablebases: None,
+        }
+    }
+
+    /// Sets `Threads`, `Hash` (in megabytes), `MultiPV` (lines to search), `EvalFile` (a
+    /// network file, `<empty>` for none) or, with the `syzygy` feature, `SyzygyPath`
+    /// (tablebase directories separated as in `PATH`, `<empty>` for none), as UCI `setoption`
+    /// would. Returns whether the option exists and the value suits it.
+    pub fn set_option(&mut self, name: &str, value: &str) -> bool {
+        let value = value.trim();
+        match (name, value.parse::<usize>()) {
+            ("Threads", Ok(threads)) if threads > 0 => self.threads = threads,
+            ("MultiPV", Ok(lines)) if lines > 0 => self.multipv = lines,
+            ("Hash", Ok(megabytes)) if megabytes > 0 => {
+                self.tt = TranspositionTable::new(megabytes)
+            }
+            ("EvalFile", _) if value.is_empty() || value == "<empty>" => self.set_network(None),
+            ("EvalFile", _) => match Network::open(value) {
+                Ok(network) => self.set_network(Some(network)),
+                Err(_) => return false,
+            },
+            #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+            ("SyzygyPath", _) if value.is_empty() || value == "<empty>" => {
+                self.set_tablebases(None)
+            }
+            #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+            ("SyzygyPath", _) => {
+                let mut tablebases = Tablebases::default();
+                for dir in std::env::split_paths(value) {
+                    if tablebases.add_directory(dir).is_err() {
+                        return false;
+                    }
+                }
+                self.set_tablebases(Some(tablebases));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Evaluates with `network` from now on, or with `eval::evaluate` without one.
+    pub fn set_network(&mut self, network: Option<Network>) {
+        self.network = network;
+        self.tt.clear();
+    }
+
+    /// Probes `tablebases` in the endgames they cover from now on.
+    #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+    pub fn set_tablebases(&mut self, tablebases: Option<Tablebases>) {
+        self.tablebases = tablebases;
+        self.tt.clear();
+    }
+
+    /// Threads a search runs on: always one on wasm, which cannot spawn any.
+    pub fn threads(&self) -> usize {
+        if cfg!(target_arch = "wasm32") {
+            1
+        } else {
+            self.threads
+        }
+    }
+
+    /// Lines `search` looks for.
+    pub fn multipv(&self) -> usize {
+        self.multipv
+    }
+
+    /// Forgets everything learnt in earlier searches.
+    pub fn clear(&self) {
+        self.tt.clear();
+    }
+
+    /// Searches one more ply at a time until `limits` run out. The result is that of the last
+    /// depth searched to the end, except that the first depth always completes.
+    ///
+    /// With more than one thread, helpers search the same tree alongside (Lazy SMP), half of
+    /// them a ply ahead, and help only through the transposition table. They stop when the
+    /// main thread is done, and the result is the main thread's. Helpers look for the best
+    /// line only, whatever `MultiPV` is.
+    pub fn search(&self, game: &Game, limits: Limits) -> SearchResult {
+        let timer = limits
+            .time
+            .map(|time| TimeManager::new(&time, game.side_to_move()));
+        let deadline = limits
+            .movetime
+            .map(|movetime| Instant::now() + movetime)
+            .into_iter()
+            .chain(timer.as_ref().map(TimeManager::deadline))
+            .min();
+        let mut moves = game.moves();
+        order(game, &mut moves);
+        // only the moves that keep the best result the tables know of
+        #[cfg(all(feature = "syzygy", not(target_arch = "wasm32")))]
+        if let Some(best) = self.tablebases.as_ref().and_then(|tb| tb.root_moves(game)) {
+            moves.retain(|m| best.contains(m));
+        }
+        if moves.is_empty() {
+            return SearchResult {
+                best_move: None,
+                score: Searcher::terminal(game, 0).unwrap_or(0),
+                depth: 0,
+                nodes: 0,
+                lines: vec![],
+            };
+        }
+        let max_depth = limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
+
+        let stop = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.threads())
+                .map(|id| {
+                    let (moves, stop) = (moves.clone(), &stop);
+                    let first_depth = (1 + id as u32 % 2).min(max_depth);
+                    scope.spawn(move || {
+                        let mut helper = Searcher::new(self, stop);
+                        helper.iterate(game, moves, first_depth..=max_depth, 1, None, None);
+                        helper.nodes
+                    })
+                })
+                .collect();
+            let mut main = Searcher::new(self, &stop);
+            let depths = 1..=max_depth;
+            let (found, depth) = main.iterate(game, moves, depths, self.multipv, deadline, timer);
+            stop.store(true, Ordering::Relaxed);
+            let mut nodes = main.nodes;
+            for helper in helpers {
+                nodes += helper.join().unwrap();
+            }
+            let lines: Vec<Line> = found
+                .into_iter()
+                .map(|(m, score)| Line {
+                    moves: self.principal_variation(game, m, depth),
+                    score,
+                    depth,
+                })
+                .collect();
+            SearchResult {
+                best_move: lines.first().map(|line| line.moves[0]),
+                score: lines.first().map_or(0, |line| line.score),
+                depth,
+                nodes,
+                lines,
+            }
+        })
+    }
+
+    /// `first`, then the best moves the hash table holds after it, up to `depth` moves.
+    fn principal_variation(&self, game: &Game, first: Move, depth: u32) -> Vec<Move> {
+        let mut line = vec![first];
+        let mut game = game.clone();
+        let mut next = Some(first);
+        while let Some((from, to, promotion)) = next {
+            game.make_move(from, to, promotion);
+            if line.len() >= depth as usize {
+                break;
+            }
+            next = self
+                .tt
+                .probe(position_key(&game))
+                .and_then(|entry| entry.best_move)
+                .filter(|m| game.moves().contains(m));
+            line.extend(next);
+        }
+        line
+    }
+}
+
+/// Searches `game` on one thread with a fresh hash table, see `Engine::search`.
+pub fn search(game: &Game, limits: Limits) -> SearchResult {
+    Engine::new().search(game, limits)
+}
+
+/// `lines` of a search from `game`, as JSON: `[{"uci": ["e2e4", ...], "san": ["e4", ...],
+/// "score": 35, "mate": null, "depth": 6}, ...]`. `mate` counts moves, negative when the side
+/// to move is the one mated, and `score` is in centipawns.
+pub fn lines_json(game: &Game, lines: &[Line]) -> String {
+    #[derive(Serialize)]
+    struct Json {
+        uci: Vec<String>,
+        san: Vec<String>,
+        score: i32,
+        mate: Option<i32>,
+        depth: u32,
+    }
+    let json: Vec<Json> = lines
+        .iter()
+        .map(|line| {
+            let (mut uci, mut san) = (vec![], vec![]);
+            let mut game = game.clone();
+            for &(from, to, promotion) in &line.moves {
+                uci.push(game.uci_move(from, to, promotion));
+                san.push(game.make_move(from, to, promotion).unwrap().san);
+            }
+            Json {
+                uci,
+                san,
+                score: line.score,
+                mate: mate_in(line.score),
+                depth: line.depth,
+            }
+        })
+        .collect();
+    serde_json::to_string(&json).unwrap()
+}
+
+/// Plies `Game::analyse` searches when not told.
+#[cfg(feature = "wasm")]
+const ANALYSIS_DEPTH: u32 = 6;
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl Game {
+    /// The best `lines` moves and what follows them, searched to `depth` plies (6 if left
+    /// out), as the JSON of `search::lines_json`.
+    pub fn analyse(&self, lines: usize, depth: Option<u32>) -> String {
+        let mut engine = Engine::new();
+        engine.set_option("MultiPV", &lines.max(1).to_string());
+        let limits = Limits {
+            depth: Some(depth.unwrap_or(ANALYSIS_DEPTH)),
+            ..Default::default()
+        };
+        lines_json(self, &engine.search(self, limits).lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(fen: &str, depth: u32) -> SearchResult {
+        let game = Game::from_fen(fen);
+        search(
+            &game,
+            Limits {
+                depth: Some(depth),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let result = best("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", 3);
+        assert_eq!(result.best_move, Some((56, 0, None)));
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn mate_in_two() {
+        let result = best(
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+            4,
+        );
+        assert_eq!(result.score, MATE - 3);
+    }
+
+    #[test]
+    fn wins_material() {
+        let result = best("4k3/8/8/3q4/8/8/3R4/3K4 w - - 0 1", 2);
+        assert_eq!(result.best_move, Some((51, 27, None)));
+        assert!(result.score > 300);
+    }
+
+    #[test]
+    fn parse_go() {
+        let limits =
+            Limits::parse_go("go wtime 60000 btime 58000 winc 1000 binc 1000 movestogo 12");
+        assert_eq!(
+            limits.time,
+            Some(TimeLeft {
+                wtime: Duration::from_secs(60),
+                btime: Duration::from_secs(58),
+                winc: Duration::from_secs(1),
+                binc: Duration::from_secs(1),
+                movestogo: Some(12),
+            })
+        );
+        assert_eq!(limits.depth, None);
+        let limits = Limits::parse_go("go infinite depth 6 movetime 500");
+        assert_eq!(limits.depth, Some(6));
+        assert_eq!(limits.movetime, Some(Duration::from_millis(500)));
+        assert_eq!(limits.time, None);
+    }
+
+    #[test]
+    fn clocked_search() {
+        let game =
+            Game::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let start = Instant::now();
+        let result = search(&game, Limits::parse_go("go wtime 3000 btime 3000"));
+        assert!(result.best_move.is_some());
+        assert!(start.elapsed() < Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn threads_agree_on_mates() {
+        for threads in [1, 2, 4] {
+            let mut engine = Engine::new();
+            assert!(engine.set_option("Threads", &threads.to_string()));
+            let limits = Limits {
+                depth: Some(4),
+                ..Default::default()
+            };
+            let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+            let result = engine.search(&game, limits);
+            assert_eq!(result.best_move, Some((56, 0, None)), "{threads} threads");
+            assert_eq!(result.score, MATE - 1, "{threads} threads");
+
+            engine.clear();
+            let game =
+                Game::from_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1");
+            let result = engine.search(&game, limits);
+            assert_eq!(result.score, MATE - 3, "{threads} threads");
+            assert_eq!(result.best_move, Some((27, 21, None)), "{threads} threads");
+        }
+    }
+
+    #[test]
+    fn options() {
+        let mut engine = Engine::new();
+        assert!(engine.set_option("Hash", "1"));
+        assert!(engine.set_option("Threads", "3"));
+        assert_eq!(engine.threads(), 3);
+        assert!(!engine.set_option("Threads", "0"));
+        assert!(!engine.set_option("Threads", "many"));
+        assert!(!engine.set_option("Ponder", "1"));
+        assert!(!engine.set_option("EvalFile", "/nonexistent/net.bin"));
+        assert!(engine.set_option("EvalFile", "<empty>"));
+    }
+
+    #[test]
+    fn network_search() {
+        // a network of zeros scores everything level, which leaves only the mate to find
+        let mut engine = Engine::new();
+        let mut file = crate::nnue::MAGIC.to_vec();
+        file.extend(crate::nnue::VERSION.to_le_bytes());
+        file.extend(8u32.to_le_bytes());
+        file.resize(file.len() + 2 * (771 * 8 + 1), 0);
+        engine.set_network(Some(Network::from_bytes(&file).unwrap()));
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        let limits = Limits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let result = engine.search(&game, limits);
+        assert_eq!(
+            (result.best_move, result.score),
+            (Some((56, 0, None)), MATE - 1)
+        );
+    }
+
+    #[test]
+    fn multipv() {
+        let mut engine = Engine::new();
+        assert!(engine.set_option("MultiPV", "3"));
+        assert!(!engine.set_option("MultiPV", "0"));
+        assert_eq!(engine.multipv(), 3);
+        let limits = Limits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        // two mates in one and a queen up
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R3R1K1 w - - 0 1");
+        let result = engine.search(&game, limits);
+        let lines = &result.lines;
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[0].score, lines[1].score), (MATE - 1, MATE - 1));
+        assert!(lines[2].score < MATE - 100);
+        let firsts: Vec<Move> = lines.iter().map(|line| line.moves[0]).collect();
+        assert!(firsts.contains(&(56, 0, None)) && firsts.contains(&(60, 4, None)));
+        assert_ne!(firsts[2], firsts[0]);
+        assert_ne!(firsts[2], firsts[1]);
+        assert_eq!(
+            (result.best_move, result.score),
+            (Some(firsts[0]), lines[0].score)
+        );
+        assert!(lines.iter().all(|line| line.depth == result.depth));
+
+        let json = lines_json(&game, &lines[..1]);
+        assert!(json.contains(r#""mate":1"#), "{json}");
+        assert!(json.contains(r#""san":["R"#), "{json}");
+        let info = result.uci_info(&game);
+        assert_eq!(info.len(), 3);
+        let (from, to, promotion) = lines[1].moves[0];
+        let (depth, nodes) = (result.depth, result.nodes);
+        assert_eq!(
+            info[1],
+            format!(
+                "info depth {depth} multipv 2 score mate 1 nodes {nodes} pv {}",
+                game.uci_move(from, to, promotion)
+            )
+        );
+        assert!(info[2].contains(" multipv 3 score cp "));
+
+        // more lines than moves
+        assert!(engine.set_option("MultiPV", "10"));
+        let game = Game::from_fen("7k/8/8/8/8/8/8/K7 w - - 0 1");
+        let result = engine.search(&game, limits);
+        assert_eq!(result.lines.len(), 3);
+        assert!(result.lines.iter().all(|line| line.moves.len() == 3));
+    }
+
+    #[test]
+    fn no_moves() {
+        let result = best("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+        let result = best("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(result.score, -MATE);
+    }
+}
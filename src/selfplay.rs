@@ -0,0 +1,268 @@
+//! Games between two engine configurations, for testing changes against each other.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::gamestate::Game;
+use crate::letterbox::Color;
+use crate::search::{Engine, Limits};
+use crate::tt::position_key;
+use crate::variant::Outcome;
+
+/// Games still going after this many plies are drawn.
+const MAX_PLIES: usize = 600;
+
+/// An engine set up with its options, and how long it thinks on each move.
+pub struct Player {
+    pub name: String,
+    pub engine: Engine,
+    pub limits: Limits,
+}
+
+impl Player {
+    /// A player from comma separated `name=value` pairs: `depth` and `movetime` (in
+    /// milliseconds) limit each search, and anything else is an engine option, e.g.
+    /// `depth=6,Hash=64,EvalFile=net.bin`.
+    pub fn parse(name: &str, spec: &str) -> Result<Player, String> {
+        let mut player = Player {
+            name: name.to_string(),
+            engine: Engine::new(),
+            limits: Limits::default(),
+        };
+        for pair in spec.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (option, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected name=value, got {pair}"))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("{option} needs a number"))
+            };
+            match option.trim() {
+                "depth" => player.limits.depth = Some(number()? as u32),
+                "movetime" => player.limits.movetime = Some(Duration::from_millis(number()?)),
+                option if player.engine.set_option(option, value) => {}
+                option => return Err(format!("bad option {option}={value}")),
+            }
+        }
+        if player.limits.depth.is_none() && player.limits.movetime.is_none() {
+            player.limits.movetime = Some(Duration::from_millis(100));
+        }
+        Ok(player)
+    }
+}
+
+/// A finished game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Played {
+    pub fen: String,
+    pub variant: String,
+    pub white: String,
+    pub black: String,
+    /// The moves in SAN.
+    pub moves: Vec<String>,
+    /// Nothing when the game had to be abandoned.
+    pub outcome: Option<Outcome>,
+    pub termination: String,
+}
+
+impl Played {
+    /// `1-0`, `0-1`, `1/2-1/2` or `*`.
+    pub fn result(&self) -> &'static str {
+        match self.outcome {
+            Some(Outcome::Winner(Color::White)) => "1-0",
+            Some(Outcome::Winner(Color::Black)) => "0-1",
+            Some(Outcome::Draw) => "1/2-1/2",
+            None => "*",
+        }
+    }
+
+    pub fn to_pgn(&self, round: usize) -> String {
+        let mut pgn = String::new();
+        let variant = match self.variant.as_str() {
+            "standard" => String::new(),
+            name => format!("[Variant \"{name}\"]\n"),
+        };
+        write!(
+            pgn,
+            "[Event \"Self-play\"]\n[Site \"local\"]\n[Date \"????.??.??\"]\n\
+             [Round \"{round}\"]\n[White \"{}\"]\n[Black \"{}\"]\n[Result \"{}\"]\n\
+             {variant}[FEN \"{}\"]\n[SetUp \"1\"]\n[Termination \"{}\"]\n\n",
+            self.white,
+            self.black,
+            self.result(),
+            self.fen,
+            self.termination
+        )
+        .unwrap();
+
+        let mut fields = self.fen.split_whitespace().skip(1);
+        let black_first = fields.next() == Some("b");
+        let mut number: u32 = fields.nth(3).and_then(|n| n.parse().ok()).unwrap_or(1);
+        let mut line = String::new();
+        for (ply, san) in self.moves.iter().enumerate() {
+            let white = (ply % 2 == 0) != black_first;
+            let token = match (white, ply) {
+                (true, _) => format!("{number}. {san}"),
+                (false, 0) => format!("{number}... {san}"),
+                (false, _) => san.clone(),
+            };
+            if !white {
+                number += 1;
+            }
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                pgn += &line;
+                pgn += "\n";
+                line.clear();
+            }
+            if !line.is_empty() {
+                line += " ";
+            }
+            line += &token;
+        }
+        if !line.is_empty() {
+            line += " ";
+        }
+        pgn + &line + self.result() + "\n\n"
+    }
+}
+
+/// Draws that `Game` leaves to the players: the fifty-move rule, repetitions and bare kings.
+fn adjudicate(game: &Game, seen: &HashMap<u64, u32>) -> Option<&'static str> {
+    let bitboards = &game.bitboards;
+    if bitboards.whites | bitboards.blacks == bitboards.kings && game.variant() != "horde" {
+        Some("insufficient material")
+    } else if game.position().halfmove >= 100 {
+        Some("fifty-move rule")
+    } else if seen.get(&position_key(game)) >= Some(&3) {
+        Some("threefold repetition")
+    } else {
+        None
+    }
+}
+
+/// Plays one game from `opening`, each engine searching from scratch. Fails in variants
+/// with drops, which the search does not play.
+pub fn play(opening: &Game, white: &Player, black: &Player) -> Result<Played, String> {
+    if opening.variant.has_pockets() {
+        return Err(format!(
+            "the engine does not play drops, so {} cannot be played",
+            opening.variant()
+        ));
+    }
+    let mut game = opening.clone();
+    let mut played = Played {
+        fen: opening.to_fen(),
+        variant: opening.variant(),
+        white: white.name.clone(),
+        black: black.name.clone(),
+        moves: vec![],
+        outcome: None,
+        termination: "unterminated".to_string(),
+    };
+    white.engine.clear();
+    black.engine.clear();
+
+    let mut seen = HashMap::from([(position_key(&game), 1)]);
+    loop {
+        // mates, stalemates and variant endings all end the game by its rules
+        if let Some(outcome) = game.outcome() {
+            played.outcome = Some(outcome);
+            played.termination = "normal".to_string();
+            break;
+        }
+        if let Some(reason) = adjudicate(&game, &seen) {
+            played.outcome = Some(Outcome::Draw);
+            played.termination = reason.to_string();
+            break;
+        }
+        if played.moves.len() >= MAX_PLIES {
+            played.outcome = Some(Outcome::Draw);
+            played.termination = "adjudicated: too long".to_string();
+            break;
+        }
+        let player = match game.side_to_move() {
+            Color::White => white,
+            Color::Black => black,
+        };
+        let Some((from, to, promotion)) = player.engine.search(&game, player.limits).best_move
+        else {
+            played.termination = format!("{} returned no move", player.name);
+            break;
+        };
+        let result = game
+            .input_move(from, to, promotion)
+            .expect("the search returned an illegal move");
+        played.moves.push(result.san);
+        *seen.entry(position_key(&game)).or_default() += 1;
+    }
+    Ok(played)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn players() {
+        let player = Player::parse("new", "depth=3, Hash=1,Threads=2").unwrap();
+        assert_eq!(
+            (player.limits.depth, player.limits.movetime),
+            (Some(3), None)
+        );
+        assert_eq!(player.engine.threads(), 2);
+        let player = Player::parse("base", "").unwrap();
+        assert_eq!(player.limits.movetime, Some(Duration::from_millis(100)));
+        assert!(Player::parse("bad", "depth=deep").is_err());
+        assert!(Player::parse("bad", "Ponder=1").is_err());
+        assert!(Player::parse("bad", "depth").is_err());
+    }
+
+    #[test]
+    fn games() {
+        let white = Player::parse("a", "depth=2").unwrap();
+        let black = Player::parse("b", "depth=2").unwrap();
+
+        // mate in one for black
+        let opening = Game::from_fen("6k1/8/8/8/8/8/r4PPP/6K1 b - - 0 7");
+        let played = play(&opening, &white, &black).unwrap();
+        assert_eq!(played.outcome, Some(Outcome::Winner(Color::Black)));
+        assert_eq!(played.moves, ["Ra1#"]);
+        assert_eq!(played.termination, "normal");
+        let pgn = played.to_pgn(3);
+        assert!(pgn.starts_with(
+            "[Event \"Self-play\"]\n[Site \"local\"]\n[Date \"????.??.??\"]\n[Round \"3\"]\n"
+        ));
+        assert!(pgn.contains("[FEN \"6k1/8/8/8/8/8/r4PPP/6K1 b - - 0 7\"]\n"));
+        assert!(pgn.ends_with("\n\n7... Ra1# 0-1\n\n"));
+
+        // bare kings are drawn before any move
+        let opening = Game::from_fen("k7/8/8/8/8/8/8/K7 w - - 0 1");
+        let played = play(&opening, &white, &black).unwrap();
+        assert_eq!(played.result(), "1/2-1/2");
+        assert_eq!(played.termination, "insufficient material");
+        assert!(played.to_pgn(1).ends_with("\n\n1/2-1/2\n\n"));
+
+        // stalemate
+        let opening = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        let played = play(&opening, &white, &black).unwrap();
+        assert_eq!(played.result(), "1/2-1/2");
+        assert_eq!(played.termination, "normal");
+    }
+
+    #[test]
+    fn no_drops() {
+        let player = Player::parse("a", "depth=1").unwrap();
+        let opening = Game::new(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            Some("crazyhouse".to_string()),
+        )
+        .unwrap();
+        let error = play(&opening, &player, &player).unwrap_err();
+        assert_eq!(
+            error,
+            "the engine does not play drops, so crazyhouse cannot be played"
+        );
+    }
+}
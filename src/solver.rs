@@ -0,0 +1,203 @@
+//! Proves mates in a given number of moves, for composing and checking problems. Unlike
+//! `search`, nothing is pruned or estimated: every defence is tried against every attack,
+//! so a solution is a proof and a failure a refutation. Drops are tried alongside moves on
+//! both sides.
+
+use crate::gamestate::{Game, Step};
+use crate::letterbox::Color;
+use crate::variant::Outcome;
+
+/// A winning attack and how it goes on against each defence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    /// The attacking move or drop: the key move at the top of the tree.
+    pub key: Step,
+    /// Every legal defence, each with the attack that still wins in time. Empty when `key`
+    /// ends the game.
+    pub defences: Vec<(Step, Solution)>,
+}
+
+impl Solution {
+    /// Moves the attacker needs against the best defence.
+    pub fn moves(&self) -> u32 {
+        1 + self
+            .defences
+            .iter()
+            .map(|(_, solution)| solution.moves())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The tree in SAN from `game`, each defence on a line of its own under the move it
+    /// answers:
+    ///
+    /// ```text
+    /// 1. Nf6+
+    ///   1... gxf6 2. Bxf7#
+    /// ```
+    pub fn format(&self, game: &Game) -> String {
+        let mut text = String::new();
+        self.write(game, 0, true, &mut text);
+        text
+    }
+
+    fn write(&self, game: &Game, indent: usize, number: bool, text: &mut String) {
+        let fullmove = game.position().fullmove;
+        let mover = game.side_to_move();
+        let mut game = game.clone();
+        let san = game.make_step(self.key).unwrap().san;
+        match (mover, number) {
+            (Color::White, _) => *text += &format!("{fullmove}. {san}"),
+            (Color::Black, true) => *text += &format!("{fullmove}... {san}"),
+            (Color::Black, false) => *text += &san,
+        }
+        for &(defence, ref solution) in &self.defences {
+            let fullmove = game.position().fullmove;
+            let mut after = game.clone();
+            let san = after.make_step(defence).unwrap().san;
+            *text += &format!("\n{:indent$}", "", indent = indent + 2);
+            match game.side_to_move() {
+                Color::White => *text += &format!("{fullmove}. {san} "),
+                Color::Black => *text += &format!("{fullmove}... {san} "),
+            }
+            solution.write(&after, indent + 2, false, text);
+        }
+    }
+}
+
+/// The shortest forced win for the side to move in at most `n` moves, or nothing if the
+/// defence holds out longer. In standard chess the win is a mate; variants count any win.
+pub fn solve(game: &Game, n: u32) -> Option<Solution> {
+    (1..=n).find_map(|moves| attack(game, moves))
+}
+
+/// The legal moves and drops of `game`, checks first.
+fn checks_first(game: &Game) -> Vec<(Step, Game)> {
+    let mut children: Vec<(Step, Game)> = game
+        .steps()
+        .into_iter()
+        .map(|step| {
+            let mut child = game.clone();
+            child.make_step(step);
+            (step, child)
+        })
+        .collect();
+    children.sort_by_key(|(_, child)| !child.is_check());
+    children
+}
+
+/// A move of the side to move that wins within `n` moves whatever the defence.
+fn attack(game: &Game, n: u32) -> Option<Solution> {
+    let attacker = game.side_to_move();
+    for (key, child) in checks_first(game) {
+        match child.outcome() {
+            Some(Outcome::Winner(winner)) if winner == attacker => {
+                return Some(Solution {
+                    key,
+                    defences: vec![],
+                })
+            }
+            Some(_) => continue,
+            None if n == 1 => continue,
+            None => {}
+        }
+        if let Some(defences) = defend(&child, n - 1) {
+            return Some(Solution { key, defences });
+        }
+    }
+    None
+}
+
+/// Answers to every defence of the side to move that still win within `n` moves, nothing if
+/// one of them holds.
+fn defend(game: &Game, n: u32) -> Option<Vec<(Step, Solution)>> {
+    let defender = game.side_to_move();
+    let children = checks_first(game);
+    // no way on that the game counts as over, whatever the variant, is no mate
+    if children.is_empty() {
+        return None;
+    }
+    let mut defences = vec![];
+    for (defence, child) in children {
+        match child.outcome() {
+            // defences that lose on the spot, as some do in Atomic, need no answer
+            Some(Outcome::Winner(winner)) if winner != defender => continue,
+            Some(_) => return None,
+            None => defences.push((defence, attack(&child, n)?)),
+        }
+    }
+    Some(defences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::letterbox::Piece;
+
+    #[test]
+    fn mate_in_one() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        let solution = solve(&game, 3).unwrap();
+        assert_eq!(solution.key, Step::Move((56, 0, None)));
+        assert_eq!(solution.moves(), 1);
+        assert_eq!(solution.format(&game), "1. Ra8#");
+    }
+
+    #[test]
+    fn mate_in_two() {
+        let game =
+            Game::from_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1");
+        assert_eq!(solve(&game, 1), None);
+        let solution = solve(&game, 2).unwrap();
+        assert_eq!(solution.key, Step::Move((27, 21, None)));
+        assert_eq!(solution.moves(), 2);
+        // every defence is answered with a mate
+        let mut check = game.clone();
+        check.make_move(27, 21, None);
+        assert_eq!(solution.defences.len(), check.moves().len());
+        for (_, reply) in &solution.defences {
+            assert!(reply.defences.is_empty());
+        }
+        let text = solution.format(&game);
+        assert_eq!(text, "1. Nf6+\n  1... gxf6 2. Bxf7#");
+    }
+
+    #[test]
+    fn refutations() {
+        // checks are not enough, and stalemate is no mate
+        let game = Game::from_fen("7k/8/6K1/8/8/8/8/6Q1 w - - 0 1");
+        assert_eq!(solve(&game, 1), None);
+        let solution = solve(&game, 2).unwrap();
+        let mut after = game.clone();
+        after.make_step(solution.key);
+        assert!(!after.is_over());
+        // a lone king mates no one
+        assert_eq!(
+            solve(&Game::from_fen("7k/8/6K1/8/8/8/8/8 w - - 0 1"), 2),
+            None
+        );
+        // black to move and mate
+        let game = Game::from_fen("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1");
+        assert_eq!(solve(&game, 1).unwrap().format(&game), "1... Ra1#");
+    }
+
+    #[test]
+    fn drops() {
+        // Ra8+ is answered by dropping the knight on f8
+        let game = Game::new(
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1[n] w - - 0 1",
+            Some("crazyhouse".to_string()),
+        )
+        .unwrap();
+        assert_eq!(solve(&game, 2), None);
+        // and the attacker may drop too
+        let game = Game::new(
+            "6k1/5ppp/8/8/8/8/5PPP/6K1[R] w - - 0 1",
+            Some("crazyhouse".to_string()),
+        )
+        .unwrap();
+        let solution = solve(&game, 1).unwrap();
+        assert_eq!(solution.key, Step::Drop(Piece::Rook, 0));
+        assert_eq!(solution.format(&game), "1. R@a8#");
+    }
+}
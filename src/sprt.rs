@@ -0,0 +1,110 @@
+//! Sequential probability ratio test on match results: whether a change gains at least `elo1`
+//! or at most `elo0`, stopping as soon as the games played tell them apart.
+
+/// Expected score of the stronger side at an Elo difference of `elo`.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// Chance of accepting `elo1` when `elo0` holds.
+    pub alpha: f64,
+    /// Chance of accepting `elo0` when `elo1` holds.
+    pub beta: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The change is no better than `elo0`.
+    H0,
+    /// The change gains at least `elo1`.
+    H1,
+}
+
+impl Sprt {
+    /// Lower and upper bounds of the log-likelihood ratio.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// Log-likelihood ratio of the two hypotheses after these results of the tested side,
+    /// scored as normally distributed around their mean.
+    pub fn llr(&self, wins: u32, draws: u32, losses: u32) -> f64 {
+        let n = (wins + draws + losses) as f64;
+        if wins == 0 || losses == 0 {
+            // no variance to speak of yet: count one more half point each way
+            return self.llr(wins + 1, draws, losses + 1) * n / (n + 2.0);
+        }
+        let (w, d, l) = (wins as f64 / n, draws as f64 / n, losses as f64 / n);
+        let mean = w + d / 2.0;
+        let variance =
+            w * (1.0 - mean).powi(2) + d * (0.5 - mean).powi(2) + l * (0.0 - mean).powi(2);
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        n * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    /// The hypothesis the results settle on, if any yet.
+    pub fn verdict(&self, wins: u32, draws: u32, losses: u32) -> Option<Verdict> {
+        let llr = self.llr(wins, draws, losses);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(Verdict::H1)
+        } else if llr <= lower {
+            Some(Verdict::H0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Elo difference that the score of these results implies, infinite after a clean sweep.
+pub fn elo(wins: u32, draws: u32, losses: u32) -> f64 {
+    let n = (wins + draws + losses).max(1) as f64;
+    let score = (wins as f64 + draws as f64 / 2.0) / n;
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPRT: Sprt = Sprt {
+        elo0: 0.0,
+        elo1: 10.0,
+        alpha: 0.05,
+        beta: 0.05,
+    };
+
+    #[test]
+    fn bounds() {
+        let (lower, upper) = SPRT.bounds();
+        assert!((lower + 2.944).abs() < 1e-3);
+        assert!((upper - 2.944).abs() < 1e-3);
+    }
+
+    #[test]
+    fn verdicts() {
+        assert_eq!(SPRT.verdict(0, 0, 0), None);
+        assert!((SPRT.llr(100, 100, 100) - SPRT.llr(1, 1, 1) * 100.0).abs() < 1e-9);
+        // an even match leans towards no gain, a lopsided one towards the gain
+        assert!(SPRT.llr(300, 400, 300) < 0.0);
+        assert_eq!(SPRT.verdict(3000, 4000, 3000), Some(Verdict::H0));
+        assert!(SPRT.llr(350, 400, 250) > 0.0);
+        assert_eq!(SPRT.verdict(3500, 4000, 2500), Some(Verdict::H1));
+        assert_eq!(SPRT.verdict(10, 0, 0), None);
+        assert!(SPRT.llr(10, 0, 0) > 0.0);
+    }
+
+    #[test]
+    fn elo_of_scores() {
+        assert_eq!(elo(5, 10, 5), 0.0);
+        assert!((elo(3, 0, 1) - 190.85).abs() < 0.01);
+        assert_eq!(elo(1, 0, 0), f64::INFINITY);
+    }
+}
@@ -0,0 +1,1541 @@
+//! Syzygy endgame tablebases found in local directories. Native builds only, behind the
+//! `syzygy` feature.
+//!
+//! Tables are discovered by their magic bytes and read into memory the first time a position
+//! needs them. The decoding follows the layout of the generator (and of the probing code
+//! shipped with Stockfish): every table holds one or two compressed value streams per file of
+//! the leading pawn, indexed by a canonical numbering of the piece placements. Squares in this
+//! module count from a1 = 0 as the tables do, unlike the a8 = 0 of the rest of the crate.
+
+use std::collections::HashMap;
+use std::ops::Neg;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::bitboards::*;
+use crate::gamestate::{Game, Move};
+use crate::letterbox::Color;
+use crate::variant::Outcome;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+/// Flags of a value stream. `STM` is the side to move a DTZ table stores, black if set.
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// Piece counts of a side in tablebase order, king first and pawns last.
+fn sides(bitboards: &BitBoards, color: u64) -> [u32; 6] {
+    [
+        bitboards.kings,
+        bitboards.queens,
+        bitboards.rooks,
+        bitboards.bishops,
+        bitboards.knights,
+        bitboards.pawns,
+    ]
+    .map(|pieces| (pieces & color).count_ones())
+}
+
+fn side_name(counts: &[u32; 6]) -> String {
+    counts
+        .iter()
+        .zip("KQRBNP".chars())
+        .flat_map(|(&count, letter)| std::iter::repeat_n(letter, count as usize))
+        .collect()
+}
+
+/// Name of the table covering the material on the board, stronger side first, e.g. `KRPvKR`.
+pub fn material_key(bitboards: &BitBoards) -> String {
+    let whites = sides(bitboards, bitboards.whites);
+    let blacks = sides(bitboards, bitboards.blacks);
+    let strength = |counts: &[u32; 6]| (counts.iter().sum::<u32>(), *counts);
+    let (first, second) = if strength(&whites) >= strength(&blacks) {
+        (whites, blacks)
+    } else {
+        (blacks, whites)
+    };
+    format!("{}v{}", side_name(&first), side_name(&second))
+}
+
+/// Result of a position with the side to move, counting the fifty-move rule as
+/// cursed wins and blessed losses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Wdl> {
+        Some(match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            2 => Wdl::Win,
+            _ => return None,
+        })
+    }
+
+    /// Distance to zeroing of a position whose best move zeroes the fifty-move counter.
+    fn before_zeroing(self) -> i32 {
+        match self {
+            Wdl::Loss => -1,
+            Wdl::BlessedLoss => -101,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 101,
+            Wdl::Win => 1,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32)).unwrap()
+    }
+}
+
+/// The material a table covers, read from its name. The side named first is white to the table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Material {
+    first: [u32; 6],
+    second: [u32; 6],
+}
+
+impl Material {
+    fn parse(key: &str) -> Option<Material> {
+        let counts = |name: &str| {
+            let mut counts = [0; 6];
+            for letter in name.chars() {
+                counts["KQRBNP".find(letter)?] += 1;
+            }
+            Some(counts).filter(|counts| counts[0] == 1)
+        };
+        let (first, second) = key.split_once('v')?;
+        Some(Material {
+            first: counts(first)?,
+            second: counts(second)?,
+        })
+    }
+
+    fn pieces(&self) -> usize {
+        (self.first.iter().sum::<u32>() + self.second.iter().sum::<u32>()) as usize
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.first[5] + self.second[5] > 0
+    }
+
+    /// Both sides have the same pieces, so only white to move is stored.
+    fn symmetric(&self) -> bool {
+        self.first == self.second
+    }
+
+    /// Some piece other than a king is alone of its kind and color.
+    fn has_unique_pieces(&self) -> bool {
+        self.first[1..].contains(&1) || self.second[1..].contains(&1)
+    }
+
+    /// Pawns of the leading color, the one with fewer pawns when both have some, then of
+    /// the other.
+    fn pawn_counts(&self) -> [u32; 2] {
+        let (white, black) = (self.first[5], self.second[5]);
+        if black == 0 || (white > 0 && black >= white) {
+            [white, black]
+        } else {
+            [black, white]
+        }
+    }
+}
+
+fn off_diagonal(square: usize) -> i32 {
+    (square >> 3) as i32 - (square & 7) as i32
+}
+
+/// Numberings the position indices are built from.
+struct Indices {
+    /// Squares below the a1-h8 diagonal to 0..28.
+    b1h1h7: [usize; 64],
+    /// The a1-d1-d4 triangle to 0..10, the diagonal last.
+    a1d1d4: [usize; 64],
+    /// The 462 placements of two kings, the first in the triangle.
+    kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    /// Squares a2-h7 to 0..48, the edge files and the lower ranks highest.
+    pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+impl Indices {
+    fn new() -> Indices {
+        let mut indices = Indices {
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+        let below = (0..64).filter(|&square| off_diagonal(square) < 0);
+        for (code, square) in below.enumerate() {
+            indices.b1h1h7[square] = code;
+        }
+
+        let triangle = (0..=27).filter(|&square| square & 7 <= 3);
+        let (diagonal, below): (Vec<usize>, Vec<usize>) = triangle
+            .filter(|&square| off_diagonal(square) <= 0)
+            .partition(|&square| off_diagonal(square) == 0);
+        for (code, &square) in below.iter().chain(&diagonal).enumerate() {
+            indices.a1d1d4[square] = code;
+        }
+
+        let mut code = 0;
+        let mut both_on_diagonal = vec![];
+        for idx in 0..10 {
+            // b1 is the only square of the triangle coded 0
+            let first = (0..=27).find(|&square| {
+                indices.a1d1d4[square] == idx && (idx > 0 || square == 1) && square & 7 <= 3
+            });
+            let Some(first) = first else { continue };
+            for second in 0..64 {
+                let apart =
+                    (first & 7).abs_diff(second & 7) > 1 || (first >> 3).abs_diff(second >> 3) > 1;
+                if !apart || off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                    continue;
+                } else if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((idx, second));
+                } else {
+                    indices.kk[idx][second] = code;
+                    code += 1;
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            indices.kk[idx][second] = code;
+            code += 1;
+        }
+
+        indices.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                indices.binomial[k][n] = if k > 0 {
+                    indices.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n { indices.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut available = 48;
+        for leads in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..=6 {
+                    let square = 8 * rank + file;
+                    if leads == 1 {
+                        indices.pawns[square] = available - 1;
+                        indices.pawns[square ^ 7] = available - 2;
+                        available -= 2;
+                    }
+                    indices.lead_pawn_idx[leads][square] = idx;
+                    idx += indices.binomial[leads - 1][indices.pawns[square]];
+                }
+                indices.lead_pawns_size[leads][file] = idx;
+            }
+        }
+        indices
+    }
+}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(Indices::new)
+}
+
+fn byte(bytes: &[u8], at: usize) -> Option<u8> {
+    bytes.get(at).copied()
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_be(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// One compressed value stream: positions of one side to move and one file of the leading
+/// pawn. Offsets point into the bytes of the table file.
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    /// Piece codes (pawn 1 to king 6, black + 8) in the order the index is built from.
+    pieces: [u8; 7],
+    /// Pieces of each group of the index, zero-terminated.
+    group_len: [usize; 8],
+    /// Factor of each group in the index; the one after the last group is the table size.
+    group_idx: [u64; 8],
+    /// Length of the shortest code, or the value of a stream holding a single one.
+    min_len: u8,
+    block_size: u64,
+    /// Values between two entries of the sparse index.
+    span: u64,
+    sparse_index: usize,
+    sparse_index_size: u64,
+    /// Values in each block, minus one, padded so that the sparse index stays in range.
+    block_lengths: usize,
+    block_lengths_size: u64,
+    /// First symbol of each code length.
+    lowest_sym: usize,
+    /// Each code length's lowest code, left-aligned.
+    base64: Vec<u64>,
+    /// Values each symbol stands for, minus one.
+    symlen: Vec<u32>,
+    /// Pairs of symbols each symbol expands to, or its value for a leaf.
+    btree: usize,
+    blocks: u64,
+    data: usize,
+    /// Where the DTZ values of each result start, when the stream maps them.
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    /// Splits the pieces in groups and sizes them, `order` telling the place of the leading
+    /// group and, with pawns on both sides, of the other pawns.
+    fn set_groups(&mut self, material: &Material, order: [usize; 2], file: usize) -> Option<()> {
+        let indices = indices();
+        let has_pawns = material.has_pawns();
+        let mut first_len = match (has_pawns, material.has_unique_pieces()) {
+            (true, _) => 0,
+            (false, true) => 3,
+            (false, false) => 2,
+        };
+        let mut n = 0;
+        self.group_len = [0; 8];
+        self.group_len[0] = 1;
+        for i in 1..material.pieces() {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+
+        let both_pawns = has_pawns && material.pawn_counts()[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free = 64 - self.group_len[0] - if both_pawns { self.group_len[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    *indices.lead_pawns_size.get(self.group_len[0])?.get(file)?
+                } else if material.has_unique_pieces() {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                self.group_idx[1] = idx;
+                idx *= indices.binomial.get(self.group_len[1])?[48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = idx;
+                idx *= indices.binomial.get(self.group_len[next])?[free];
+                free -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = idx;
+        Some(())
+    }
+
+    /// Reads the header of the stream at `at`, returning where the next one starts.
+    fn set_sizes(&mut self, bytes: &[u8], mut at: usize) -> Option<usize> {
+        self.flags = byte(bytes, at)?;
+        if self.flags & SINGLE_VALUE != 0 {
+            self.min_len = byte(bytes, at + 1)?;
+            return Some(at + 2);
+        }
+        let size = self.group_idx[self.group_len.iter().position(|&len| len == 0)?];
+        self.block_size = 1u64.checked_shl(byte(bytes, at + 1)?.into())?;
+        self.span = 1u64.checked_shl(byte(bytes, at + 2)?.into())?;
+        self.sparse_index_size = size.div_ceil(self.span);
+        let padding = u64::from(byte(bytes, at + 3)?);
+        self.blocks = u64::from(u32_le(bytes, at + 4)?);
+        self.block_lengths_size = self.blocks + padding;
+        let max_len = byte(bytes, at + 8)? as usize;
+        self.min_len = byte(bytes, at + 9)?;
+        let min_len = self.min_len as usize;
+        if min_len == 0 || max_len < min_len || max_len >= 64 {
+            return None;
+        }
+        at += 10;
+        self.lowest_sym = at;
+
+        // longer codes have lower values, so that the lowest code of each length, padded to
+        // 64 bits, tells the length of the next code in the stream
+        let mut base64 = vec![0u64; max_len - min_len + 1];
+        for i in (0..base64.len() - 1).rev() {
+            let lowest = u64::from(u16_le(bytes, at + 2 * i)?);
+            let next = u64::from(u16_le(bytes, at + 2 * i + 2)?);
+            base64[i] = base64[i + 1].wrapping_add(lowest).wrapping_sub(next) / 2;
+        }
+        for (i, base) in base64.iter_mut().enumerate() {
+            *base <<= 64 - i - min_len;
+        }
+        at += 2 * base64.len();
+        self.base64 = base64;
+
+        let symbols = u16_le(bytes, at)? as usize;
+        at += 2;
+        self.btree = at;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(bytes, sym, &mut visited)?;
+            }
+        }
+        Some(at + 3 * symbols + (symbols & 1))
+    }
+
+    fn set_symlen(&mut self, bytes: &[u8], sym: usize, visited: &mut [bool]) -> Option<u32> {
+        visited[sym] = true;
+        let (left, right) = self.pair(bytes, sym)?;
+        if right == 0xfff {
+            return Some(0);
+        }
+        for side in [left, right] {
+            if !*visited.get(side)? {
+                self.symlen[side] = self.set_symlen(bytes, side, visited)?;
+            }
+        }
+        Some(self.symlen[left] + self.symlen[right] + 1)
+    }
+
+    /// The two symbols `sym` stands for, the second 0xfff for a leaf holding the first.
+    fn pair(&self, bytes: &[u8], sym: usize) -> Option<(usize, usize)> {
+        let at = self.btree + 3 * sym;
+        let [a, b, c] = bytes.get(at..at + 3)? else {
+            return None;
+        };
+        Some((
+            (usize::from(b & 0xf) << 8) | usize::from(*a),
+            (usize::from(*c) << 4) | usize::from(b >> 4),
+        ))
+    }
+
+    /// The value stored at `index`.
+    fn decompress(&self, bytes: &[u8], index: u64) -> Option<i32> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.min_len.into());
+        }
+        let k = index / self.span;
+        if k >= self.sparse_index_size {
+            return None;
+        }
+        let entry = self.sparse_index + 6 * k as usize;
+        let mut block = u32_le(bytes, entry)? as usize;
+        let mut offset = i64::from(u16_le(bytes, entry + 4)?) + (index % self.span) as i64
+            - (self.span / 2) as i64;
+        let length = |block: usize| u16_le(bytes, self.block_lengths + 2 * block).map(i64::from);
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += length(block)? + 1;
+        }
+        while offset > length(block)? {
+            offset -= length(block)? + 1;
+            block += 1;
+        }
+        if block as u64 >= self.blocks {
+            return None;
+        }
+
+        let mut at = self.data + block * self.block_size as usize;
+        let mut buffer = u64_be(bytes, at)?;
+        at += 8;
+        let mut bits = 64;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buffer < self.base64[len] {
+                len += 1;
+            }
+            let code = (buffer - self.base64[len]) >> (64 - len - self.min_len as usize);
+            sym = code as usize + usize::from(u16_le(bytes, self.lowest_sym + 2 * len)?);
+            let values = i64::from(*self.symlen.get(sym)?) + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            len += self.min_len as usize;
+            buffer <<= len;
+            bits -= len;
+            if bits <= 32 {
+                bits += 32;
+                // the last block may end before the word does
+                buffer |= u64::from(u32_be(bytes, at).unwrap_or(0)) << (64 - bits);
+                at += 4;
+            }
+        }
+
+        while self.symlen[sym] != 0 {
+            let (left, right) = self.pair(bytes, sym)?;
+            let values = i64::from(*self.symlen.get(left)?) + 1;
+            if offset < values {
+                sym = left;
+            } else {
+                offset -= values;
+                sym = right;
+            }
+            self.symlen.get(sym)?;
+        }
+        Some(self.pair(bytes, sym)?.0 as i32)
+    }
+}
+
+/// A table file read into memory.
+struct Table {
+    bytes: Vec<u8>,
+    material: Material,
+    /// Streams by the side to move they store, then by the file of the leading pawn.
+    pairs: Vec<Vec<PairsData>>,
+}
+
+impl Table {
+    fn read(path: &Path, material: &Material, dtz: bool) -> Option<Table> {
+        Table::parse(std::fs::read(path).ok()?, material.clone(), dtz)
+    }
+
+    fn parse(bytes: Vec<u8>, material: Material, dtz: bool) -> Option<Table> {
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        let flags = byte(&bytes, 4)?;
+        if bytes[..4] != magic
+            || (flags & 1 != 0) == material.symmetric()
+            || (flags & 2 != 0) != material.has_pawns()
+        {
+            return None;
+        }
+        let sides = if !dtz && !material.symmetric() { 2 } else { 1 };
+        let files = if material.has_pawns() { 4 } else { 1 };
+        let both_pawns = material.has_pawns() && material.pawn_counts()[1] > 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+
+        let mut at = 5;
+        for file in 0..files {
+            let first = byte(&bytes, at)?;
+            let second = if both_pawns {
+                byte(&bytes, at + 1)?
+            } else {
+                0xff
+            };
+            at += 1 + both_pawns as usize;
+            for k in 0..material.pieces() {
+                let codes = byte(&bytes, at + k)?;
+                for (side, pairs) in pairs.iter_mut().enumerate() {
+                    pairs[file].pieces[k] = if side == 0 { codes & 0xf } else { codes >> 4 };
+                }
+            }
+            at += material.pieces();
+            for (side, pairs) in pairs.iter_mut().enumerate() {
+                let order = [first, second].map(|byte| (byte >> (4 * side) & 0xf) as usize);
+                pairs[file].set_groups(&material, order, file)?;
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for pairs in &mut pairs {
+                at = pairs[file].set_sizes(&bytes, at)?;
+            }
+        }
+        if dtz {
+            for pairs in &mut pairs[0] {
+                if pairs.flags & MAPPED == 0 {
+                    continue;
+                }
+                for map in &mut pairs.map_idx {
+                    if pairs.flags & WIDE != 0 {
+                        at += at & 1;
+                        *map = at + 2;
+                        at += 2 * u16_le(&bytes, at)? as usize + 2;
+                    } else {
+                        *map = at + 1;
+                        at += byte(&bytes, at)? as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+        for file in 0..files {
+            for pairs in &mut pairs {
+                pairs[file].sparse_index = at;
+                at += 6 * pairs[file].sparse_index_size as usize;
+            }
+        }
+        for file in 0..files {
+            for pairs in &mut pairs {
+                pairs[file].block_lengths = at;
+                at += 2 * pairs[file].block_lengths_size as usize;
+            }
+        }
+        for file in 0..files {
+            for pairs in &mut pairs {
+                at = (at + 0x3f) & !0x3f;
+                pairs[file].data = at;
+                at += (pairs[file].blocks * pairs[file].block_size) as usize;
+            }
+        }
+        (at <= bytes.len()).then_some(Table {
+            bytes,
+            material,
+            pairs,
+        })
+    }
+
+    /// The stream and the index of a position, with the file of its leading pawn. Nothing for
+    /// a DTZ table that only stores the other side to move.
+    fn locate(&self, game: &Game, dtz: bool) -> Option<(&PairsData, usize, u64)> {
+        let bitboards = &game.bitboards;
+        let black_to_move = game.turn == Color::Black;
+        // the tables have the stronger side white, and only white to move when both sides
+        // are alike, so the board is turned around otherwise
+        let flip = self.material.symmetric() && black_to_move
+            || sides(bitboards, bitboards.whites) != self.material.first;
+        let stm = (flip != black_to_move) as usize;
+        let mut placed = vec![];
+        for square in 0..64 {
+            if let Some((piece, color)) = bitboards.piece_at(square ^ 56) {
+                let code = piece as u8 + if color == Color::Black { 8 } else { 0 };
+                placed.push(if flip {
+                    (square ^ 56, code ^ 8)
+                } else {
+                    (square, code)
+                });
+            }
+        }
+        self.encode(stm, &placed, dtz)
+    }
+
+    /// `locate` for pieces already turned around as the table sees them, as squares from
+    /// a1 = 0 and piece codes.
+    fn encode(
+        &self,
+        stm: usize,
+        placed: &[(usize, u8)],
+        dtz: bool,
+    ) -> Option<(&PairsData, usize, u64)> {
+        let indices = indices();
+        let has_pawns = self.material.has_pawns();
+        let lead = has_pawns.then(|| self.pairs[0][0].pieces[0]);
+        let mut squares: Vec<usize> = placed
+            .iter()
+            .filter(|&&(_, code)| Some(code) == lead)
+            .map(|&(square, _)| square)
+            .collect();
+        let mut file = 0;
+        if has_pawns {
+            // the pawn nearest the edge, and the lowest of them, leads
+            let leading = (0..squares.len()).max_by_key(|&i| indices.pawns[squares[i]])?;
+            squares.swap(0, leading);
+            file = (squares[0] & 7).min(7 - (squares[0] & 7));
+        }
+        let leads = squares.len();
+        let stored = self.pairs[0][file].flags & STM == stm as u8;
+        if dtz && !stored && (has_pawns || !self.material.symmetric()) {
+            return None;
+        }
+
+        let mut pieces = vec![0; leads];
+        for &(square, code) in placed.iter().filter(|&&(_, code)| Some(code) != lead) {
+            squares.push(square);
+            pieces.push(code);
+        }
+        let pairs = &self.pairs[stm % self.pairs.len()][file];
+        let size = squares.len();
+        for i in leads..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| pieces[j] == pairs.pieces[i]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+        if squares[0] & 7 > 3 {
+            squares.iter_mut().for_each(|square| *square ^= 7);
+        }
+
+        let mut index;
+        if has_pawns {
+            index = indices.lead_pawn_idx[leads][squares[0]];
+            squares[1..leads].sort_by_key(|&square| indices.pawns[square]);
+            for (i, &square) in squares[..leads].iter().enumerate().skip(1) {
+                index += indices.binomial[i][indices.pawns[square]];
+            }
+        } else {
+            if squares[0] >> 3 > 3 {
+                squares.iter_mut().for_each(|square| *square ^= 56);
+            }
+            // the first piece of the leading group off the diagonal goes below it
+            for i in 0..pairs.group_len[0] {
+                match off_diagonal(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => squares[i..]
+                        .iter_mut()
+                        .for_each(|square| *square = ((*square >> 3) | (*square << 3)) & 63),
+                    _ => {}
+                }
+                break;
+            }
+            index = if self.material.has_unique_pieces() {
+                let [first, second, third] = [0, 1, 2].map(|i| squares[i] as u64);
+                let rank = |square: u64| square >> 3;
+                let adjust1 = (second > first) as u64;
+                let adjust2 = (third > first) as u64 + (third > second) as u64;
+                let below = |square: u64| indices.b1h1h7[square as usize] as u64;
+                if off_diagonal(squares[0]) != 0 {
+                    (indices.a1d1d4[squares[0]] as u64 * 63 + second - adjust1) * 62 + third
+                        - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank(first) * 28 + below(second)) * 62 + third - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(first) * 7 * 28
+                        + (rank(second) - adjust1) * 28
+                        + below(third)
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(first) * 7 * 6
+                        + (rank(second) - adjust1) * 6
+                        + (rank(third) - adjust2)
+                }
+            } else {
+                indices.kk[indices.a1d1d4[squares[0]]][squares[1]]
+            };
+        }
+
+        // the other groups, each as a combination of the squares the earlier ones left
+        index *= pairs.group_idx[0];
+        let mut start = pairs.group_len[0];
+        let mut other_pawns = has_pawns && self.material.pawn_counts()[1] > 0;
+        for next in (1..8).take_while(|&next| pairs.group_len[next] != 0) {
+            let len = pairs.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+                n += indices.binomial[i + 1][square - adjust - 8 * other_pawns as usize];
+            }
+            other_pawns = false;
+            index += n * pairs.group_idx[next];
+            start += len;
+        }
+        Some((pairs, file, index))
+    }
+
+    /// Plies to zeroing of a DTZ table value for a position of result `wdl`.
+    fn dtz_score(&self, file: usize, value: i32, wdl: Wdl) -> Option<i32> {
+        let pairs = &self.pairs[0][file];
+        let mut value = value;
+        if pairs.flags & MAPPED != 0 {
+            let map = pairs.map_idx[[1, 3, 0, 2, 0][(wdl as i32 + 2) as usize]];
+            value = if pairs.flags & WIDE != 0 {
+                u16_le(&self.bytes, map + 2 * value as usize)?.into()
+            } else {
+                byte(&self.bytes, map + value as usize)?.into()
+            };
+        }
+        let plies = match wdl {
+            Wdl::Win => pairs.flags & WIN_PLIES != 0,
+            Wdl::Loss => pairs.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        Some(if plies { value } else { 2 * value } + 1)
+    }
+}
+
+/// A table found on disk, read when first probed.
+struct TableFile {
+    path: PathBuf,
+    material: Material,
+    table: OnceLock<Option<Table>>,
+}
+
+impl TableFile {
+    fn table(&self, dtz: bool) -> Option<&Table> {
+        self.table
+            .get_or_init(|| Table::read(&self.path, &self.material, dtz))
+            .as_ref()
+    }
+}
+
+fn is_zeroing(game: &Game, (from, to, _): Move) -> bool {
+    game.bitboards.color(!game.turn) & (1 << to) != 0 || game.bitboards.pawns & (1 << from) != 0
+}
+
+fn is_capture(game: &Game, (from, to, _): Move) -> bool {
+    game.bitboards.color(!game.turn) & (1 << to) != 0
+        || game.enpassant == Some(to) && game.bitboards.pawns & (1 << from) != 0
+}
+
+fn after(game: &Game, (from, to, promotion): Move) -> Option<Game> {
+    let mut child = game.clone();
+    child.make_move(from, to, promotion)?;
+    Some(child)
+}
+
+#[derive(Default)]
+pub struct Tablebases {
+    wdl: HashMap<String, TableFile>,
+    dtz: HashMap<String, TableFile>,
+    max_pieces: u32,
+}
+
+impl Tablebases {
+    /// Collects the `.rtbw` and `.rtbz` files of a directory, skipping anything without the
+    /// Syzygy magic bytes.
+    pub fn open(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut tablebases = Self::default();
+        tablebases.add_directory(dir)?;
+        Ok(tablebases)
+    }
+
+    /// Adds the tables of another directory, the ones already found taking precedence.
+    pub fn add_directory(&mut self, dir: impl AsRef<Path>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+                continue;
+            };
+            let (tables, magic) = match extension.to_str() {
+                Some("rtbw") => (&mut self.wdl, WDL_MAGIC),
+                Some("rtbz") => (&mut self.dtz, DTZ_MAGIC),
+                _ => continue,
+            };
+            let mut header = [0; 4];
+            let valid = std::fs::File::open(&path)
+                .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+                .is_ok_and(|_| header == magic);
+            let Some(key) = stem.to_str().filter(|_| valid) else {
+                continue;
+            };
+            let Some(material) = Material::parse(key) else {
+                continue;
+            };
+            self.max_pieces = self.max_pieces.max(material.pieces() as u32);
+            tables.entry(key.to_owned()).or_insert(TableFile {
+                path,
+                material,
+                table: OnceLock::new(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn max_pieces(&self) -> u32 {
+        self.max_pieces
+    }
+
+    /// Whether the position is a standard one without castling rights and small enough
+    /// for the tables found.
+    pub fn covers(&self, game: &Game) -> bool {
+        let bitboards = &game.bitboards;
+        game.variant.name() == "standard"
+            && game.castling == 0
+            && (bitboards.whites | bitboards.blacks).count_ones() <= self.max_pieces.max(2)
+    }
+
+    pub fn wdl_table(&self, game: &Game) -> Option<&Path> {
+        self.wdl
+            .get(&material_key(&game.bitboards))
+            .map(|file| file.path.as_path())
+    }
+
+    pub fn dtz_table(&self, game: &Game) -> Option<&Path> {
+        self.dtz
+            .get(&material_key(&game.bitboards))
+            .map(|file| file.path.as_path())
+    }
+
+    /// The result of the position, `None` if it is not covered or a table it needs is
+    /// missing or unreadable.
+    pub fn probe_wdl(&self, game: &Game) -> Option<Wdl> {
+        if !self.covers(game) {
+            return None;
+        }
+        self.search(game, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Distance to a zeroing move under the fifty-move rule, signed like `Wdl`: plies to a
+    /// capture or pawn move that keeps the result, 1 when the next move mates or zeroes,
+    /// and over 100 for cursed wins and blessed losses. The count may be one ply more than
+    /// the shortest, as tables that store moves rather than plies cannot tell.
+    pub fn probe_dtz(&self, game: &Game) -> Option<i32> {
+        if !self.covers(game) {
+            return None;
+        }
+        let (wdl, zeroing) = self.search(game, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(wdl.before_zeroing());
+        }
+        if let Some(dtz) = self.probe_dtz_table(game, wdl)? {
+            let fifty = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss) as i32;
+            return Some((dtz + 100 * fifty) * (wdl as i32).signum());
+        }
+
+        // the table stores the other side to move: take the best reply
+        let mut best = None;
+        for m in game.moves() {
+            let zeroing = is_zeroing(game, m);
+            let child = after(game, m)?;
+            let mut dtz = if zeroing {
+                -self.search(&child, false)?.0.before_zeroing()
+            } else {
+                -self.probe_dtz(&child)?
+            };
+            if dtz == 1 && child.outcome() == Some(Outcome::Winner(game.turn)) {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == (wdl as i32).signum() && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        // no moves: mated
+        Some(best.unwrap_or(-1))
+    }
+
+    /// The legal moves that keep the best result the tables promise, the ones reaching the
+    /// next capture or pawn move the soonest when winning and the latest when losing.
+    /// `None` if the position is not covered or a table is missing.
+    pub fn root_moves(&self, game: &Game) -> Option<Vec<Move>> {
+        if !self.covers(game) {
+            return None;
+        }
+        let mut ranked = vec![];
+        for m in game.moves() {
+            let child = after(game, m)?;
+            let dtz = if child.outcome() == Some(Outcome::Winner(game.turn)) {
+                1
+            } else if child.halfmove == 0 {
+                (-self.probe_wdl(&child)?).before_zeroing()
+            } else if child.halfmove >= 100 {
+                0
+            } else {
+                let dtz = -self.probe_dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            let rank = match dtz {
+                0 => 0,
+                dtz if dtz > 0 => 2000 - dtz,
+                dtz => -2000 - dtz,
+            };
+            ranked.push((m, rank));
+        }
+        let best = ranked.iter().map(|&(_, rank)| rank).max()?;
+        Some(
+            ranked
+                .into_iter()
+                .filter(|&(_, rank)| rank == best)
+                .map(|(m, _)| m)
+                .collect(),
+        )
+    }
+
+    /// The result of the position, with captures, which the tables leave out, searched
+    /// through, and pawn moves too if `zeroing`. The flag tells whether such a move is
+    /// the best.
+    fn search(&self, game: &Game, zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = game.moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for &m in &moves {
+            if !(is_capture(game, m) || zeroing && is_zeroing(game, m)) {
+                continue;
+            }
+            searched += 1;
+            let value = -self.search(&after(game, m)?, false)?.0;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+        let exhausted = searched > 0 && searched == moves.len();
+        let value = if exhausted {
+            best
+        } else {
+            self.probe_wdl_table(game)?
+        };
+        if best >= value {
+            return Some((best, best > Wdl::Draw || exhausted));
+        }
+        Some((value, false))
+    }
+
+    fn probe_wdl_table(&self, game: &Game) -> Option<Wdl> {
+        if (game.bitboards.whites | game.bitboards.blacks).count_ones() == 2 {
+            return Some(Wdl::Draw);
+        }
+        let table = self.wdl.get(&material_key(&game.bitboards))?.table(false)?;
+        let (pairs, _, index) = table.locate(game, false)?;
+        Wdl::from_value(pairs.decompress(&table.bytes, index)? - 2)
+    }
+
+    /// The DTZ table's value, or `Some(None)` if the table only stores the other side to move.
+    fn probe_dtz_table(&self, game: &Game, wdl: Wdl) -> Option<Option<i32>> {
+        let table = self.dtz.get(&material_key(&game.bitboards))?.table(true)?;
+        let Some((pairs, file, index)) = table.locate(game, true) else {
+            return Some(None);
+        };
+        let value = pairs.decompress(&table.bytes, index)?;
+        table.dtz_score(file, value, wdl).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn material_keys() {
+        for (fen, key) in [
+            ("8/8/8/4k3/8/8/8/4K3 w - - 0 1", "KvK"),
+            ("8/8/8/4k3/8/8/1Q6/4K3 w - - 0 1", "KQvK"),
+            ("8/8/3r4/4k3/8/8/1Q6/4K3 w - - 0 1", "KQvKR"),
+            ("8/8/3rp3/4k3/8/8/1R6/4K3 b - - 0 1", "KRPvKR"),
+            ("8/8/3b4/4k3/8/8/1R6/4K3 w - - 0 1", "KRvKB"),
+            ("8/8/3n4/4k3/4P3/8/8/4K3 w - - 0 1", "KNvKP"),
+            ("8/8/3p4/4k3/4P3/8/8/4K3 w - - 0 1", "KPvKP"),
+        ] {
+            assert_eq!(material_key(&Game::from_fen(fen).bitboards), key, "{fen}");
+        }
+        let material = Material::parse("KRPvKR").unwrap();
+        assert_eq!(material.pieces(), 5);
+        assert!(material.has_pawns() && material.has_unique_pieces());
+        assert_eq!(material.pawn_counts(), [1, 0]);
+        assert!(Material::parse("KKvK").is_none());
+    }
+
+    #[test]
+    fn index_tables() {
+        let indices = indices();
+        let kings: Vec<u64> = indices.kk.iter().flatten().copied().collect();
+        assert_eq!(kings.iter().max(), Some(&461));
+        assert_eq!(indices.a1d1d4[1], 0);
+        assert_eq!(indices.a1d1d4[27], 9);
+        assert_eq!(indices.b1h1h7[55], 27);
+        assert_eq!(indices.binomial[2][62], 1891);
+        // a2, h2, a3, ...
+        assert_eq!(
+            [8, 15, 16].map(|square| indices.pawns[square]),
+            [47, 46, 45]
+        );
+        assert_eq!(indices.lead_pawns_size[1], [6; 4]);
+    }
+
+    /// A table of `material` with streams of `pieces` led by one group, for encoding only.
+    fn layout(name: &str, pieces: &[u8]) -> Table {
+        let material = Material::parse(name).unwrap();
+        let files = if material.has_pawns() { 4 } else { 1 };
+        let pairs = (0..files)
+            .map(|file| {
+                let mut pairs = PairsData::default();
+                pairs.pieces[..pieces.len()].copy_from_slice(pieces);
+                let other_pawns = if material.pawn_counts()[1] > 0 {
+                    1
+                } else {
+                    0xf
+                };
+                pairs.set_groups(&material, [0, other_pawns], file).unwrap();
+                pairs
+            })
+            .collect();
+        Table {
+            bytes: vec![],
+            material,
+            pairs: vec![pairs],
+        }
+    }
+
+    /// Checks on random placements of `pieces` that the index stays below the table size,
+    /// that mirrored placements share it, and that no other placement does.
+    fn check_encoding(name: &str, pieces: &[u8]) {
+        let table = layout(name, pieces);
+        let transforms: &[fn(usize) -> usize] = if table.material.has_pawns() {
+            &[|s| s, |s| s ^ 7]
+        } else {
+            &[
+                |s| s,
+                |s| s ^ 7,
+                |s| s ^ 56,
+                |s| s ^ 63,
+                |s| ((s >> 3) | (s << 3)) & 63,
+                |s| (((s >> 3) | (s << 3)) & 63) ^ 7,
+                |s| (((s >> 3) | (s << 3)) & 63) ^ 56,
+                |s| (((s >> 3) | (s << 3)) & 63) ^ 63,
+            ]
+        };
+        let lead = table.pairs[0][0].group_len[0];
+        let mut seen: HashMap<(usize, u64), Vec<(usize, u8)>> = HashMap::new();
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut samples = 0;
+        while samples < 20_000 {
+            let placed: Vec<(usize, u8)> = pieces
+                .iter()
+                .map(|&code| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    ((seed >> 32) as usize % 64, code)
+                })
+                .collect();
+            let kings: Vec<usize> = placed
+                .iter()
+                .filter(|p| p.1 & 7 == 6)
+                .map(|p| p.0)
+                .collect();
+            let (a, b) = (kings[0], kings[1]);
+            let mut squares: Vec<usize> = placed.iter().map(|p| p.0).collect();
+            squares.sort_unstable();
+            squares.dedup();
+            if squares.len() < pieces.len()
+                || (a & 7).abs_diff(b & 7) <= 1 && (a >> 3).abs_diff(b >> 3) <= 1
+                || placed
+                    .iter()
+                    .any(|&(s, code)| code & 7 == 1 && !(8..56).contains(&s))
+            {
+                continue;
+            }
+            samples += 1;
+
+            let images: Vec<Vec<(usize, u8)>> = transforms
+                .iter()
+                .map(|transform| {
+                    placed
+                        .iter()
+                        .map(|&(s, code)| (transform(s), code))
+                        .collect()
+                })
+                .collect();
+            let canonical = images
+                .iter()
+                .map(|image| {
+                    let mut image = image.clone();
+                    image.sort_unstable();
+                    image
+                })
+                .min()
+                .unwrap();
+            let located: Vec<(usize, u64)> = images
+                .iter()
+                .map(|image| {
+                    let (pairs, file, index) = table.encode(0, image, false).unwrap();
+                    let groups = pairs.group_len.iter().position(|&len| len == 0).unwrap();
+                    assert!(index < pairs.group_idx[groups], "{name} {image:?}: {index}");
+                    (file, index)
+                })
+                .collect();
+            // with the leading group on a long diagonal, the mirror image through it is
+            // stored apart
+            let on_diagonal = |flip: usize| {
+                placed[..lead]
+                    .iter()
+                    .all(|&(s, _)| off_diagonal(s ^ flip) == 0)
+            };
+            if lead == pieces.len()
+                || table.material.has_pawns()
+                || !on_diagonal(0) && !on_diagonal(7)
+            {
+                assert!(
+                    located.iter().all(|&at| at == located[0]),
+                    "{name} {placed:?}"
+                );
+            }
+            for at in located {
+                let other = seen.entry(at).or_insert_with(|| canonical.clone());
+                assert_eq!(*other, canonical, "{name}: {at:?} taken twice");
+            }
+        }
+    }
+
+    #[test]
+    fn encodings() {
+        check_encoding("KRvK", &[4, 6, 14]);
+        check_encoding("KQvKR", &[6, 14, 5, 12]);
+        check_encoding("KRRvK", &[6, 14, 4, 4]);
+        check_encoding("KPvK", &[1, 6, 14]);
+        check_encoding("KPvKP", &[1, 9, 6, 14]);
+    }
+
+    #[test]
+    fn pairs() {
+        // codes 1, 01 and 00 for a pair of 7 and 9, a 9 and a 7
+        let mut bytes = vec![
+            0, 6, 4, 0, // 64-byte blocks, a span of 16 values, no padding
+            1, 0, 0, 0, // one block
+            2, 1, // code lengths
+            2, 0, 0, 0, // lowest symbols of each length
+            3, 0, // three symbols
+            7, 0xf0, 0xff, 9, 0xf0, 0xff, 0, 0x10, 0x00, 0, // a leaf, a leaf and a pair
+        ];
+        let mut pairs = PairsData::default();
+        pairs.group_idx[0] = 6;
+        assert_eq!(pairs.set_sizes(&bytes, 0), Some(bytes.len()));
+        assert_eq!(pairs.symlen, [0, 0, 1]);
+
+        pairs.sparse_index = bytes.len();
+        bytes.extend([0, 0, 0, 0, 8, 0]);
+        pairs.block_lengths = bytes.len();
+        bytes.extend([5, 0]);
+        bytes.resize(64, 0);
+        pairs.data = 64;
+        // 1 01 00 1
+        bytes.push(0b1010_0100);
+        bytes.resize(128, 0);
+        let values: Vec<i32> = (0..6)
+            .map(|i| pairs.decompress(&bytes, i).unwrap())
+            .collect();
+        assert_eq!(values, [7, 9, 9, 7, 7, 9]);
+        assert_eq!(pairs.decompress(&bytes, 16), None);
+    }
+
+    /// A pawnless table laid out as the generator does, each value coded in a byte of its
+    /// own: `pieces` in the order the index takes them, then the values of each stream.
+    fn write_table(name: &str, dtz: bool, pieces: &[u8], streams: &[Vec<u8>]) -> Vec<u8> {
+        let material = Material::parse(name).unwrap();
+        let mut bytes = if dtz { DTZ_MAGIC } else { WDL_MAGIC }.to_vec();
+        bytes.push(!material.symmetric() as u8);
+        bytes.push(0);
+        bytes.extend(pieces.iter().map(|&piece| piece | piece << 4));
+        bytes.resize(bytes.len().next_multiple_of(2), 0);
+        let blocks = |values: &Vec<u8>| values.len().div_ceil(1024);
+        for values in streams {
+            bytes.extend([0, 10, 10, 0]);
+            bytes.extend((blocks(values) as u32).to_le_bytes());
+            bytes.extend([8, 8, 0, 0, 0, 1]);
+            for sym in 0..=255 {
+                bytes.extend([sym, 0xf0, 0xff]);
+            }
+        }
+        for values in streams {
+            for block in 0..blocks(values) as u32 {
+                bytes.extend(block.to_le_bytes());
+                bytes.extend(512u16.to_le_bytes());
+            }
+        }
+        for values in streams {
+            for chunk in values.chunks(1024) {
+                bytes.extend((chunk.len() as u16 - 1).to_le_bytes());
+            }
+        }
+        for values in streams {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            for chunk in values.chunks(1024) {
+                let end = bytes.len() + 1024;
+                bytes.extend(chunk);
+                bytes.resize(end, 0);
+            }
+        }
+        bytes
+    }
+
+    /// The position of `placed`, squares from a1 = 0 and piece codes as the tables take them.
+    fn position(placed: &[(usize, u8)], stm: Color) -> Game {
+        let mut board = ['1'; 64];
+        for &(square, code) in placed {
+            let piece = b" PNBRQK"[code as usize & 7] as char;
+            board[square ^ 56] = if code & 8 == 0 {
+                piece
+            } else {
+                piece.to_ascii_lowercase()
+            };
+        }
+        let ranks: Vec<String> = board.chunks(8).map(|rank| rank.iter().collect()).collect();
+        let turn = if stm == Color::White { 'w' } else { 'b' };
+        Game::from_fen(&format!("{} {turn} - - 0 1", ranks.join("/")))
+    }
+
+    /// KQvK tables with the true results, captures left to the search: white to move always
+    /// wins, black to move loses unless stalemated. The DTZ of a position is twice its index
+    /// modulo 50, plus one. They are read from `dir` when first probed.
+    fn synthetic(dir: &Path) -> Tablebases {
+        let pieces = [5, 6, 14];
+        let layout = layout("KQvK", &pieces);
+        let mut black = vec![0; 31332];
+        // the queen leads, so the index takes it to the a1-d1-d4 triangle, and a lone king
+        // is only stalemated on the edge
+        let triangle = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+        let edge = (0..64).filter(|&s: &usize| s & 7 == 0 || s & 7 == 7 || !(8..56).contains(&s));
+        for (queen, foe) in edge.flat_map(|foe| triangle.map(|queen| (queen, foe))) {
+            for king in 0..64 {
+                let placed = [(queen, 5), (king, 6), (foe, 14)];
+                if queen == king
+                    || queen == foe
+                    || (king & 7).abs_diff(foe & 7) <= 1 && (king >> 3).abs_diff(foe >> 3) <= 1
+                {
+                    continue;
+                }
+                let game = position(&placed, Color::Black);
+                if game.moves().is_empty() && !game.is_check() {
+                    let (_, _, index) = layout.encode(1, &placed, false).unwrap();
+                    black[index as usize] = 2;
+                }
+            }
+        }
+        let wdl = write_table("KQvK", false, &pieces, &[vec![4; 31332], black]);
+        let dtz: Vec<u8> = (0..31332).map(|index| (index % 50) as u8).collect();
+        let dtz = write_table("KQvK", true, &pieces, &[dtz]);
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("KQvK.rtbw"), wdl).unwrap();
+        std::fs::write(dir.join("KQvK.rtbz"), dtz).unwrap();
+        Tablebases::open(dir).unwrap()
+    }
+
+    #[test]
+    fn written_tables() {
+        let dir = std::env::temp_dir().join(format!("syzygy-written-{}", std::process::id()));
+        let tablebases = synthetic(&dir);
+        let wdl = tablebases.wdl["KQvK"].table(false).unwrap();
+        for index in [0, 1, 1023, 1024, 31331] {
+            assert_eq!(wdl.pairs[0][0].decompress(&wdl.bytes, index), Some(4));
+            assert_eq!(wdl.pairs[1][0].decompress(&wdl.bytes, index), Some(0));
+        }
+        let stalemates = (0..31332)
+            .filter(|&index| wdl.pairs[1][0].decompress(&wdl.bytes, index) == Some(2))
+            .count();
+        assert!(stalemates > 0);
+        let dtz = tablebases.dtz["KQvK"].table(true).unwrap();
+        for index in [0, 49, 50, 1023, 1024, 2047, 31331] {
+            let value = dtz.pairs[0][0].decompress(&dtz.bytes, index);
+            assert_eq!(value, Some((index % 50) as i32));
+        }
+
+        let game = Game::from_fen("8/8/8/4k3/8/8/1Q6/4K3 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&game), Some(Wdl::Win));
+        let (_, _, index) = dtz.locate(&game, true).unwrap();
+        let expected = 2 * (index % 50) as i32 + 1;
+        assert_eq!(tablebases.probe_dtz(&game), Some(expected));
+        // the same with the colors swapped
+        let swapped = Game::from_fen("8/8/8/4K3/8/8/1q6/4k3 b - - 0 1");
+        let mirrored = Game::from_fen("4K3/1Q6/8/8/4k3/8/8/8 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&swapped), Some(Wdl::Win));
+        assert_eq!(
+            tablebases.probe_dtz(&swapped),
+            tablebases.probe_dtz(&mirrored)
+        );
+
+        // black to move is not stored: the longest of the replies
+        let game = Game::from_fen("8/8/8/4k3/8/8/1Q6/4K3 b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&game), Some(Wdl::Loss));
+        let longest = game
+            .moves()
+            .into_iter()
+            .map(|m| -tablebases.probe_dtz(&after(&game, m).unwrap()).unwrap() - 1)
+            .min();
+        assert_eq!(tablebases.probe_dtz(&game), longest);
+
+        // taking the queen draws, which the tables leave to a search
+        let game = Game::from_fen("8/8/8/8/8/2kQ4/8/6K1 b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&game), Some(Wdl::Draw));
+        assert_eq!(tablebases.probe_dtz(&game), Some(0));
+        let take = game.parse_uci_move("c3d3").unwrap();
+        assert_eq!(tablebases.root_moves(&game), Some(vec![take]));
+
+        let mut engine = crate::search::Engine::new();
+        engine.set_tablebases(Some(tablebases));
+        let result = engine.search(
+            &game,
+            crate::search::Limits {
+                depth: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(result.best_move, Some(take));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn engine_options() {
+        let dir = std::env::temp_dir().join(format!("syzygy-options-{}", std::process::id()));
+        let tablebases = synthetic(&dir);
+        let mut engine = crate::search::Engine::new();
+        let search = |engine: &mut crate::search::Engine, game: &Game, depth: u32| {
+            let limits = crate::search::Limits {
+                depth: Some(depth),
+                ..Default::default()
+            };
+            engine.search(game, limits)
+        };
+
+        // stalemate is a draw, whichever way round
+        for fen in [
+            "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1",
+            "8/8/8/8/8/1k6/2q5/K7 w - - 0 1",
+        ] {
+            assert_eq!(tablebases.probe_wdl(&Game::from_fen(fen)), Some(Wdl::Draw));
+        }
+        // so the root keeps the mates and drops Qc7
+        let game = Game::from_fen("k7/8/1K6/2Q5/8/8/8/8 w - - 0 1");
+        let stalemate = game.parse_uci_move("c5c7").unwrap();
+        assert!(!tablebases.root_moves(&game).unwrap().contains(&stalemate));
+        assert!(engine.set_option("SyzygyPath", dir.to_str().unwrap()));
+        let result = search(&mut engine, &game, 1);
+        let best = after(&game, result.best_move.unwrap()).unwrap();
+        assert_eq!(best.outcome(), Some(Outcome::Winner(Color::White)));
+        // and black takes the queen, the only move that holds
+        let game = Game::from_fen("8/8/8/8/8/2kQ4/8/6K1 b - - 0 1");
+        let take = game.parse_uci_move("c3d3").unwrap();
+        assert_eq!(search(&mut engine, &game, 3).best_move, Some(take));
+
+        // taking the queen or the rook reaches the tables, which cut the search short
+        let game = Game::from_fen("8/8/8/2k5/8/8/1Q1r4/4K3 b - - 0 1");
+        let probed = search(&mut engine, &game, 4);
+        assert!(engine.set_option("SyzygyPath", "<empty>"));
+        let searched = search(&mut engine, &game, 4);
+        assert_eq!(probed.best_move, searched.best_move);
+        assert!(probed.nodes < searched.nodes);
+        assert!(!engine.set_option("SyzygyPath", dir.join("missing").to_str().unwrap()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn discovery() {
+        let dir = std::env::temp_dir().join(format!("syzygy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("KQvK.rtbw"), WDL_MAGIC).unwrap();
+        std::fs::write(dir.join("KQvK.rtbz"), DTZ_MAGIC).unwrap();
+        std::fs::write(dir.join("KRPvKR.rtbw"), [0; 4]).unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        let tablebases = Tablebases::open(&dir).unwrap();
+
+        assert_eq!(tablebases.max_pieces(), 3);
+        let game = Game::from_fen("8/8/8/4k3/8/8/1Q6/4K3 b - - 0 1");
+        assert!(tablebases.covers(&game));
+        assert!(tablebases.wdl_table(&game).is_some());
+        assert!(tablebases.dtz_table(&game).is_some());
+        // a file with nothing but the magic bytes
+        assert_eq!(tablebases.probe_wdl(&game), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let game = Game::from_fen("8/8/3rp3/4k3/8/8/1R6/4K3 b - - 0 1");
+        assert!(!tablebases.covers(&game));
+        assert!(tablebases.wdl_table(&game).is_none());
+
+        let game = Game::from_fen("8/8/8/4k3/8/8/8/4K3 b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&game), Some(Wdl::Draw));
+        assert_eq!(tablebases.probe_dtz(&game), Some(0));
+        let game = Game::from_fen("r3k3/8/8/8/8/8/8/4K3 b q - 0 1");
+        assert_eq!(tablebases.probe_wdl(&game), None);
+    }
+
+    /// Against the real KQvK, KRvK, KPvK, KRvKP and KBNvK tables from the Syzygy
+    /// distribution: `cargo test --features syzygy -- --ignored` with their `.rtbw` and
+    /// `.rtbz` files in `tests/syzygy`.
+    #[test]
+    #[ignore = "needs the Syzygy KQvK, KRvK, KPvK, KRvKP and KBNvK tables in tests/syzygy"]
+    fn real_tables() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/syzygy");
+        let tablebases = Tablebases::open(&dir).unwrap();
+        assert!(tablebases.max_pieces() >= 4);
+        for (fen, wdl) in [
+            ("8/8/8/4k3/8/8/1Q6/4K3 w - - 0 1", Wdl::Win),
+            ("8/8/8/4k3/8/8/1Q6/4K3 b - - 0 1", Wdl::Loss),
+            ("8/8/8/8/8/2kQ4/8/6K1 b - - 0 1", Wdl::Draw),
+            ("8/8/8/4k3/8/8/1R6/4K3 w - - 0 1", Wdl::Win),
+            ("8/8/8/4K3/8/8/1r6/4k3 b - - 0 1", Wdl::Win),
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Draw),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+            ("8/8/8/8/8/8/4P3/4K2k w - - 0 1", Wdl::Win),
+            ("8/8/8/4k3/8/8/2NN4/4K3 w - - 0 1", Wdl::Draw),
+            ("8/8/8/4k3/8/8/8/2BNK3 w - - 0 1", Wdl::Win),
+        ] {
+            assert_eq!(
+                tablebases.probe_wdl(&Game::from_fen(fen)),
+                Some(wdl),
+                "{fen}"
+            );
+        }
+
+        let game = Game::from_fen("7k/8/6K1/8/8/8/8/Q7 w - - 0 1");
+        assert_eq!(tablebases.probe_dtz(&game), Some(1));
+        for m in tablebases.root_moves(&game).unwrap() {
+            assert!(after(&game, m).unwrap().outcome().is_some(), "{m:?}");
+        }
+
+        // best play against the longest defence mates well within fifty moves
+        for fen in [
+            "8/8/8/4k3/8/8/1R6/4K3 w - - 0 1",
+            "8/8/8/8/8/8/4P3/4K2k w - - 0 1",
+        ] {
+            let mut game = Game::from_fen(fen);
+            let mut plies = 0;
+            while game.outcome().is_none() {
+                let dtz = tablebases.probe_dtz(&game).unwrap();
+                assert_eq!(
+                    dtz.signum(),
+                    if plies % 2 == 0 { 1 } else { -1 },
+                    "{}",
+                    game.to_fen()
+                );
+                let m = tablebases.root_moves(&game).unwrap()[0];
+                game = after(&game, m).unwrap();
+                plies += 1;
+                assert!(plies < 100, "{fen}");
+            }
+            assert!(matches!(
+                game.outcome(),
+                Some(Outcome::Winner(Color::White))
+            ));
+        }
+
+        // the engine keeps to the drawing capture at the root, and the tables cut short the
+        // lines where the rook takes the pawn
+        let mut engine = crate::search::Engine::new();
+        assert!(engine.set_option("SyzygyPath", dir.to_str().unwrap()));
+        let limits = crate::search::Limits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let game = Game::from_fen("8/8/8/8/8/2kQ4/8/6K1 b - - 0 1");
+        let take = game.parse_uci_move("c3d3").unwrap();
+        assert_eq!(engine.search(&game, limits).best_move, Some(take));
+        let game = Game::from_fen("8/8/8/3k4/8/3p4/8/R5K1 w - - 0 1");
+        let probed = engine.search(&game, limits);
+        assert!(engine.set_option("SyzygyPath", "<empty>"));
+        let searched = engine.search(&game, limits);
+        assert!(probed.nodes < searched.nodes);
+    }
+}
@@ -0,0 +1,152 @@
+//! Splits the time left on the clock between the moves still to play.
+
+use std::time::{Duration, Instant};
+
+use crate::gamestate::Move;
+use crate::letterbox::Color;
+
+/// Kept back on every move for the time lost between the engine and the clock.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(20);
+/// Moves the rest of the game is assumed to last without `movestogo`.
+const MOVES_TO_GO: u32 = 30;
+/// Score drop, in centipawns, from one iteration to the next that counts as a fail-low.
+const FAIL_LOW_MARGIN: i32 = 30;
+
+/// The clocks as UCI `go` gives them: `wtime`, `btime`, `winc`, `binc` and `movestogo`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimeLeft {
+    pub wtime: Duration,
+    pub btime: Duration,
+    pub winc: Duration,
+    pub binc: Duration,
+    /// Moves until the next time control, sudden death if `None`.
+    pub movestogo: Option<u32>,
+}
+
+/// Decides when iterative deepening should stop. The soft limit is checked between
+/// iterations and shrinks while the best move holds; the hard limit aborts a search midway.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    soft: Duration,
+    hard: Duration,
+    /// Best move and score of the last iteration.
+    last: Option<(Move, i32)>,
+    /// Iterations in a row that kept the same best move.
+    stable: u32,
+}
+
+impl TimeManager {
+    /// Budget for a move of `side`, starting now.
+    pub fn new(time: &TimeLeft, side: Color) -> Self {
+        let (remaining, increment) = match side {
+            Color::White => (time.wtime, time.winc),
+            Color::Black => (time.btime, time.binc),
+        };
+        let available = remaining.saturating_sub(MOVE_OVERHEAD);
+        let moves = time.movestogo.unwrap_or(MOVES_TO_GO).max(1);
+        // never stake more than four fifths of the clock on one move
+        let cap = available * 4 / 5;
+        let soft = (available / moves + increment * 3 / 4).min(cap);
+        TimeManager {
+            start: Instant::now(),
+            soft,
+            hard: (soft * 4).min(cap),
+            last: None,
+            stable: 0,
+        }
+    }
+
+    pub fn soft(&self) -> Duration {
+        self.soft
+    }
+
+    pub fn hard(&self) -> Duration {
+        self.hard
+    }
+
+    /// When a running iteration must be abandoned.
+    pub fn deadline(&self) -> Instant {
+        self.start + self.hard
+    }
+
+    /// Called after each finished iteration: whether another one is worth starting.
+    pub fn keep_going(&mut self, best: Move, score: i32) -> bool {
+        self.keep_going_after(self.start.elapsed(), best, score)
+    }
+
+    fn keep_going_after(&mut self, elapsed: Duration, best: Move, score: i32) -> bool {
+        let fail_low = matches!(self.last, Some((_, last)) if score < last - FAIL_LOW_MARGIN);
+        self.stable = match self.last {
+            Some((last, _)) if last == best => self.stable + 1,
+            _ => 0,
+        };
+        self.last = Some((best, score));
+
+        // a settled best move gives back up to half the budget, a fail-low doubles it
+        let mut percent = 100 - 10 * self.stable.min(5);
+        if fail_low {
+            percent = 200;
+        }
+        elapsed < (self.soft * percent / 100).min(self.hard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOVE: Move = (52, 36, None);
+    const OTHER: Move = (51, 35, None);
+
+    fn seconds(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn budgets() {
+        let time = TimeLeft {
+            wtime: seconds(60) + MOVE_OVERHEAD,
+            btime: seconds(30) + MOVE_OVERHEAD,
+            winc: seconds(2),
+            ..Default::default()
+        };
+        let white = TimeManager::new(&time, Color::White);
+        assert_eq!(white.soft(), seconds(2) + seconds(2) * 3 / 4);
+        assert_eq!(white.hard(), white.soft() * 4);
+        let black = TimeManager::new(&time, Color::Black);
+        assert_eq!(black.soft(), seconds(1));
+
+        let last_move = TimeLeft {
+            wtime: seconds(10) + MOVE_OVERHEAD,
+            movestogo: Some(1),
+            ..Default::default()
+        };
+        let white = TimeManager::new(&last_move, Color::White);
+        assert_eq!((white.soft(), white.hard()), (seconds(8), seconds(8)));
+
+        let flagging = TimeManager::new(&TimeLeft::default(), Color::White);
+        assert_eq!(flagging.hard(), Duration::ZERO);
+    }
+
+    #[test]
+    fn stability_and_fail_lows() {
+        let time = TimeLeft {
+            wtime: seconds(300) + MOVE_OVERHEAD,
+            ..Default::default()
+        };
+        let mut manager = TimeManager::new(&time, Color::White);
+        assert_eq!(manager.soft(), seconds(10));
+
+        // the same best move over and over cuts the budget down to half
+        for _ in 0..6 {
+            assert!(manager.keep_going_after(seconds(4), MOVE, 20));
+        }
+        assert!(!manager.keep_going_after(seconds(6), MOVE, 20));
+        // a new best move restores it
+        assert!(manager.keep_going_after(seconds(6), OTHER, 20));
+        // and a falling score doubles it
+        assert!(manager.keep_going_after(seconds(15), OTHER, -40));
+        assert!(!manager.keep_going_after(seconds(15), OTHER, -40));
+    }
+}
@@ -0,0 +1,519 @@
+//! A tree of moves for analysis: the main line, side variations branching off it, and the
+//! comments and NAGs on each move. Nodes keep the whole position they lead to, so browsing
+//! never replays moves.
+
+#[cfg(feature = "wasm")]
+use js_sys::Int32Array;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::gamestate::{Game, MoveResult, Step};
+use crate::letterbox::{Color, Piece};
+
+#[derive(Clone)]
+struct Node {
+    parent: Option<usize>,
+    /// The first child continues the line, the others are variations, most important first.
+    children: Vec<usize>,
+    /// The move leading here, nothing at the root.
+    step: Option<Step>,
+    san: String,
+    game: Game,
+    comment: String,
+    nags: Vec<u8>,
+}
+
+/// Nodes are numbered in the order they are added, from 0 for the root. Numbers of deleted
+/// nodes are not reused.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone)]
+pub struct GameTree {
+    nodes: Vec<Option<Node>>,
+    current: usize,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl GameTree {
+    /// A tree holding only the starting position, `fen` and `variant` as for `Game::new`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(fen: &str, variant: Option<String>) -> Result<GameTree, String> {
+        Ok(GameTree::from_game(Game::new(fen, variant)?))
+    }
+
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    /// The node whose position is on the board.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Position at the current node.
+    pub fn game(&self) -> Game {
+        self.node(self.current).game.clone()
+    }
+
+    /// Plays a move from the current node and goes to the node it leads to: the existing one
+    /// if the move was already in the tree, a new variation (or the main line, for the first
+    /// child) otherwise. Nothing happens if the move is illegal.
+    pub fn input_move(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<Piece>,
+    ) -> Option<MoveResult> {
+        let mut game = self.game();
+        let result = game.input_move(from, to, promotion)?;
+        self.add(Step::Move((from, to, promotion)), &result, game);
+        Some(result)
+    }
+
+    /// Drops a pocket piece from the current node, like `input_move`.
+    pub fn input_drop(&mut self, piece: Piece, to: usize) -> Option<MoveResult> {
+        let mut game = self.game();
+        let result = game.input_drop(piece, to)?;
+        self.add(Step::Drop(piece, to), &result, game);
+        Some(result)
+    }
+
+    /// Goes one move back, false at the root.
+    pub fn back(&mut self) -> bool {
+        match self.node(self.current).parent {
+            Some(parent) => {
+                self.current = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Goes one move along the line, false at its end.
+    pub fn forward(&mut self) -> bool {
+        match self.node(self.current).children.first() {
+            Some(&child) => {
+                self.current = child;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn to_start(&mut self) {
+        self.current = 0;
+    }
+
+    /// Goes to the end of the line the current node is on.
+    pub fn to_end(&mut self) {
+        while self.forward() {}
+    }
+
+    /// Goes to `node`, false if there is no such node.
+    pub fn go_to(&mut self, node: usize) -> bool {
+        if self.contains(node) {
+            self.current = node;
+        }
+        self.contains(node)
+    }
+
+    pub fn contains(&self, node: usize) -> bool {
+        self.get(node).is_some()
+    }
+
+    /// Nothing for the root and for unknown nodes.
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        self.get(node)?.parent
+    }
+
+    /// Continuations of `node`, the line first.
+    pub fn children(&self, node: usize) -> Option<Vec<usize>> {
+        Some(self.get(node)?.children.clone())
+    }
+
+    /// Nodes from the root to `node`, both included.
+    pub fn path(&self, node: usize) -> Option<Vec<usize>> {
+        let mut path = vec![node];
+        while let Some(parent) = self.get(path[path.len() - 1])?.parent {
+            path.push(parent);
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Nodes of the main line, the root first.
+    pub fn main_line(&self) -> Vec<usize> {
+        let mut line = vec![0];
+        while let Some(&child) = self.node(line[line.len() - 1]).children.first() {
+            line.push(child);
+        }
+        line
+    }
+
+    /// Whether `node` is on the main line, false for unknown nodes.
+    pub fn is_main_line(&self, node: usize) -> bool {
+        self.path(node).is_some_and(|path| {
+            path.windows(2)
+                .all(|pair| self.node(pair[0]).children[0] == pair[1])
+        })
+    }
+
+    /// SAN of the move leading to `node`, empty for the root and nothing for unknown nodes.
+    pub fn san(&self, node: usize) -> Option<String> {
+        Some(self.get(node)?.san.clone())
+    }
+
+    pub fn comment(&self, node: usize) -> Option<String> {
+        Some(self.get(node)?.comment.clone())
+    }
+
+    /// False if there is no such node.
+    pub fn set_comment(&mut self, node: usize, comment: &str) -> bool {
+        self.get_mut(node)
+            .map(|node| node.comment = comment.to_string())
+            .is_some()
+    }
+
+    /// Numeric annotation glyphs of the move leading to `node`, in the order they were added.
+    pub fn nags(&self, node: usize) -> Option<Vec<u8>> {
+        Some(self.get(node)?.nags.clone())
+    }
+
+    /// False if there is no such node.
+    pub fn add_nag(&mut self, node: usize, nag: u8) -> bool {
+        let Some(node) = self.get_mut(node) else {
+            return false;
+        };
+        if !node.nags.contains(&nag) {
+            node.nags.push(nag);
+        }
+        true
+    }
+
+    /// False if there is no such node.
+    pub fn remove_nag(&mut self, node: usize, nag: u8) -> bool {
+        self.get_mut(node)
+            .map(|node| node.nags.retain(|&n| n != nag))
+            .is_some()
+    }
+
+    /// Moves the variation holding `node` one place up among its siblings, at the closest
+    /// branch where it is not the first. False if `node` is on the main line or unknown.
+    pub fn promote(&mut self, node: usize) -> bool {
+        let Some(path) = self.path(node) else {
+            return false;
+        };
+        for pair in path.windows(2).rev() {
+            let children = &mut self.node_mut(pair[0]).children;
+            let index = children.iter().position(|&c| c == pair[1]).unwrap();
+            if index > 0 {
+                children.swap(index - 1, index);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Makes the line through `node` the main line, false if there is no such node.
+    pub fn make_main_line(&mut self, node: usize) -> bool {
+        let Some(path) = self.path(node) else {
+            return false;
+        };
+        for pair in path.windows(2) {
+            let children = &mut self.node_mut(pair[0]).children;
+            let index = children.iter().position(|&c| c == pair[1]).unwrap();
+            children[..=index].rotate_right(1);
+        }
+        true
+    }
+
+    /// Deletes `node` and everything after it. If the current node goes with it, the parent
+    /// of `node` becomes current. The root cannot be deleted.
+    pub fn delete(&mut self, node: usize) -> bool {
+        if !self.contains(node) {
+            return false;
+        }
+        let Some(parent) = self.node(node).parent else {
+            return false;
+        };
+        if self.path(self.current).unwrap().contains(&node) {
+            self.current = parent;
+        }
+        self.node_mut(parent).children.retain(|&c| c != node);
+        let mut doomed = vec![node];
+        while let Some(node) = doomed.pop() {
+            doomed.extend(self.nodes[node].take().unwrap().children);
+        }
+        true
+    }
+
+    /// Movetext of the whole tree in PGN, variations in parentheses.
+    pub fn pgn(&self) -> String {
+        let mut tokens = vec![];
+        let root = self.node(0);
+        if !root.comment.is_empty() {
+            tokens.push(format!("{{{}}}", root.comment));
+        }
+        self.line_tokens(0, true, &mut tokens);
+        tokens.join(" ")
+    }
+
+    /// Delegates to the current position, so the board UI can show a tree like a game.
+    pub fn send_board(&self) -> String {
+        self.node(self.current).game.send_board()
+    }
+
+    pub fn send_pockets(&self) -> String {
+        self.node(self.current).game.send_pockets()
+    }
+
+    pub fn variant(&self) -> String {
+        self.node(self.current).game.variant()
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.node(self.current).game.to_fen()
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl GameTree {
+    pub fn get_legal_moves(&self) -> Vec<Int32Array> {
+        self.game().get_legal_moves()
+    }
+
+    pub fn get_legal_drops(&self, piece: Piece) -> Int32Array {
+        self.node(self.current).game.get_legal_drops(piece)
+    }
+}
+
+impl GameTree {
+    pub fn from_game(game: Game) -> Self {
+        GameTree {
+            nodes: vec![Some(Node {
+                parent: None,
+                children: vec![],
+                step: None,
+                san: String::new(),
+                game,
+                comment: String::new(),
+                nags: vec![],
+            })],
+            current: 0,
+        }
+    }
+
+    fn get(&self, node: usize) -> Option<&Node> {
+        self.nodes.get(node)?.as_ref()
+    }
+
+    fn get_mut(&mut self, node: usize) -> Option<&mut Node> {
+        self.nodes.get_mut(node)?.as_mut()
+    }
+
+    /// For nodes known to be in the tree, such as the current one and its relatives. Panics
+    /// on deleted and unknown nodes, as indexing would.
+    fn node(&self, node: usize) -> &Node {
+        self.get(node).expect("no such node")
+    }
+
+    fn node_mut(&mut self, node: usize) -> &mut Node {
+        self.get_mut(node).expect("no such node")
+    }
+
+    fn add(&mut self, step: Step, result: &MoveResult, game: Game) {
+        let parent = self.current;
+        let children = &self.node(parent).children;
+        if let Some(&child) = children.iter().find(|&&c| self.node(c).step == Some(step)) {
+            self.current = child;
+            return;
+        }
+        self.nodes.push(Some(Node {
+            parent: Some(parent),
+            children: vec![],
+            step: Some(step),
+            san: result.san.clone(),
+            game,
+            comment: String::new(),
+            nags: vec![],
+        }));
+        let child = self.nodes.len() - 1;
+        self.node_mut(parent).children.push(child);
+        self.current = child;
+    }
+
+    /// Move number and SAN of `node`, with its NAGs and comment.
+    fn move_tokens(&self, node: usize, number: bool, tokens: &mut Vec<String>) {
+        let parent = &self.node(self.node(node).parent.unwrap()).game;
+        let fullmove = parent.position().fullmove;
+        let white = parent.side_to_move() == Color::White;
+        let node = self.node(node);
+        let mut token = match (white, number) {
+            (true, _) => format!("{fullmove}. {}", node.san),
+            (false, true) => format!("{fullmove}... {}", node.san),
+            (false, false) => node.san.clone(),
+        };
+        for nag in &node.nags {
+            token += &format!(" ${nag}");
+        }
+        tokens.push(token);
+        if !node.comment.is_empty() {
+            tokens.push(format!("{{{}}}", node.comment));
+        }
+    }
+
+    /// The line after `node`, with the variations of each move right after that move.
+    fn line_tokens(&self, mut node: usize, mut number: bool, tokens: &mut Vec<String>) {
+        while let Some((&next, variations)) = self.node(node).children.split_first() {
+            self.move_tokens(next, number, tokens);
+            for &variation in variations {
+                let mut inner = vec![];
+                self.move_tokens(variation, true, &mut inner);
+                self.line_tokens(variation, false, &mut inner);
+                tokens.push(format!("({})", inner.join(" ")));
+            }
+            // after a variation, or a comment, black's move needs its number again
+            number = !variations.is_empty() || !self.node(next).comment.is_empty();
+            node = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// Plays `moves` in UCI notation from the current node, returning the last node.
+    fn play(tree: &mut GameTree, moves: &[&str]) -> usize {
+        for uci in moves {
+            let (from, to, promotion) = tree.game().parse_uci_move(uci).unwrap();
+            tree.input_move(from, to, promotion).unwrap();
+        }
+        tree.current()
+    }
+
+    #[test]
+    fn lines_and_variations() {
+        let mut tree = GameTree::new(START, None).unwrap();
+        let e4 = play(&mut tree, &["e2e4"]);
+        let main = play(&mut tree, &["e7e5", "g1f3"]);
+        tree.go_to(e4);
+        let sicilian = play(&mut tree, &["c7c5", "g1f3"]);
+        assert_eq!(tree.children(e4).unwrap().len(), 2);
+        assert_eq!(tree.main_line(), [0, e4, e4 + 1, main]);
+        assert!(tree.is_main_line(main));
+        assert!(!tree.is_main_line(sicilian));
+        assert_eq!(tree.path(sicilian).unwrap(), [0, e4, e4 + 3, sicilian]);
+        assert_eq!(tree.san(sicilian).unwrap(), "Nf3");
+
+        // playing a move already in the tree follows it
+        tree.go_to(e4);
+        assert_eq!(play(&mut tree, &["e7e5"]), e4 + 1);
+        assert_eq!(tree.nodes.len(), 6);
+
+        // navigation
+        tree.to_end();
+        assert_eq!(tree.current(), main);
+        assert!(tree.back());
+        assert_eq!(tree.to_fen(), tree.node(e4 + 1).game.to_fen());
+        tree.to_start();
+        assert!(!tree.back());
+        assert_eq!(tree.to_fen(), START);
+        assert!(tree.forward());
+        assert_eq!(tree.current(), e4);
+        assert!(!tree.go_to(100));
+        assert!(tree.input_move(0, 0, None).is_none());
+    }
+
+    #[test]
+    fn promote_and_delete() {
+        let mut tree = GameTree::new(START, None).unwrap();
+        let e4 = play(&mut tree, &["e2e4"]);
+        let e5 = play(&mut tree, &["e7e5"]);
+        tree.go_to(e4);
+        let c5 = play(&mut tree, &["c7c5"]);
+        let nf3 = play(&mut tree, &["g1f3"]);
+        tree.go_to(e4);
+        let e6 = play(&mut tree, &["e7e6"]);
+        assert_eq!(tree.children(e4).unwrap(), [e5, c5, e6]);
+
+        assert!(tree.promote(e6));
+        assert_eq!(tree.children(e4).unwrap(), [e5, e6, c5]);
+        assert!(tree.promote(nf3));
+        assert_eq!(tree.children(e4).unwrap(), [e5, c5, e6]);
+        assert!(tree.promote(nf3));
+        assert_eq!(tree.children(e4).unwrap(), [c5, e5, e6]);
+        assert!(!tree.promote(nf3));
+        assert!(tree.make_main_line(e5));
+        assert_eq!(tree.children(e4).unwrap(), [e5, c5, e6]);
+
+        tree.go_to(nf3);
+        assert!(tree.delete(c5));
+        assert_eq!(tree.current(), e4);
+        assert_eq!(tree.children(e4).unwrap(), [e5, e6]);
+        assert!(!tree.contains(nf3));
+        assert!(!tree.delete(c5));
+        assert!(!tree.delete(0));
+
+        // deleted and unknown nodes are refused, not panicked on
+        for gone in [c5, nf3, 100] {
+            assert_eq!(tree.san(gone), None);
+            assert_eq!(tree.comment(gone), None);
+            assert!(!tree.set_comment(gone, "lost"));
+            assert_eq!(tree.nags(gone), None);
+            assert!(!tree.add_nag(gone, 1));
+            assert!(!tree.remove_nag(gone, 1));
+            assert_eq!(tree.parent(gone), None);
+            assert_eq!(tree.children(gone), None);
+            assert_eq!(tree.path(gone), None);
+            assert!(!tree.is_main_line(gone));
+            assert!(!tree.promote(gone));
+            assert!(!tree.make_main_line(gone));
+            assert!(!tree.go_to(gone));
+        }
+    }
+
+    #[test]
+    fn annotations() {
+        let mut tree = GameTree::new(START, None).unwrap();
+        let e4 = play(&mut tree, &["e2e4"]);
+        let e5 = play(&mut tree, &["e7e5"]);
+        play(&mut tree, &["g1f3"]);
+        tree.go_to(e4);
+        let d6 = play(&mut tree, &["c7c5", "g1f3", "d7d6"]);
+        tree.go_to(e5);
+        let black = play(&mut tree, &["g1f3", "b8c6"]);
+        tree.set_comment(e4, "best by test");
+        tree.add_nag(e4, 1);
+        tree.add_nag(e4, 1);
+        tree.add_nag(e4, 14);
+        tree.remove_nag(e4, 14);
+        tree.add_nag(d6, 6);
+        assert_eq!(tree.nags(e4).unwrap(), [1]);
+        assert_eq!(tree.comment(e4).unwrap(), "best by test");
+        assert_eq!(tree.current(), black);
+        assert_eq!(
+            tree.pgn(),
+            "1. e4 $1 {best by test} 1... e5 (1... c5 2. Nf3 d6 $6) 2. Nf3 Nc6"
+        );
+    }
+
+    #[test]
+    fn drops() {
+        let mut tree = GameTree::new(
+            "4k3/8/8/8/8/8/8/4K3[Nq] w - - 0 1",
+            Some("crazyhouse".into()),
+        )
+        .unwrap();
+        tree.input_drop(Piece::Knight, 41).unwrap();
+        tree.input_drop(Piece::Queen, 44).unwrap();
+        tree.back();
+        tree.back();
+        tree.input_drop(Piece::Knight, 41).unwrap();
+        assert_eq!(tree.current(), 1);
+        assert_eq!(tree.pgn(), "1. N@b3 Q@e3+");
+        assert!(tree.input_drop(Piece::Rook, 20).is_none());
+    }
+}
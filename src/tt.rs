@@ -0,0 +1,201 @@
+//! Transposition table shared by all search threads. Entries are two atomic words, the key
+//! stored xor-ed with the data, so a torn write from a racing thread reads as a miss instead
+//! of as a wrong entry.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::gamestate::{Game, Move};
+use crate::letterbox::Piece;
+use crate::polyglot::polyglot_key;
+
+/// Scores beyond this are mates, stored relative to the node rather than to the root.
+const MATE_BOUND: i32 = crate::search::MATE - 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact = 1,
+    /// The score is at least this: the node failed high.
+    Lower = 2,
+    /// The score is at most this: no move reached alpha.
+    Upper = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub best_move: Option<Move>,
+    score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+impl Entry {
+    /// Score of the entry seen from a node `ply` plies below the root.
+    pub fn score(&self, ply: u32) -> i32 {
+        match self.score {
+            s if s > MATE_BOUND => s - ply as i32,
+            s if s < -MATE_BOUND => s + ply as i32,
+            s => s,
+        }
+    }
+
+    fn pack(&self) -> u64 {
+        let best_move = self.best_move.map_or(0, |(from, to, promotion)| {
+            1 << 15 | (from as u64) << 9 | (to as u64) << 3 | promotion.map_or(0, |p| p as u64)
+        });
+        best_move
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth.min(255) as u64) << 32
+            | (self.bound as u64) << 40
+    }
+
+    fn unpack(data: u64) -> Option<Entry> {
+        let bound = match data >> 40 & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        let best_move = (data & 1 << 15 != 0).then(|| {
+            let promotion = (data & 7) as usize;
+            (
+                (data >> 9 & 63) as usize,
+                (data >> 3 & 63) as usize,
+                promotion.checked_sub(1).map(|i| Piece::ALL[i]),
+            )
+        });
+        Some(Entry {
+            best_move,
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: (data >> 32 & 255) as u32,
+            bound,
+        })
+    }
+}
+
+pub struct TranspositionTable {
+    slots: Vec<[AtomicU64; 2]>,
+}
+
+impl TranspositionTable {
+    /// A table taking about `megabytes` of memory, at least one entry.
+    pub fn new(megabytes: usize) -> Self {
+        let len = (megabytes << 20) / std::mem::size_of::<[AtomicU64; 2]>();
+        TranspositionTable {
+            slots: (0..len.max(1))
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+        }
+    }
+
+    pub fn clear(&self) {
+        for [key, data] in &self.slots {
+            key.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, key: u64) -> &[AtomicU64; 2] {
+        &self.slots[((key as u128 * self.slots.len() as u128) >> 64) as usize]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let [stored, data] = self.slot(key);
+        let data = data.load(Ordering::Relaxed);
+        if stored.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        Entry::unpack(data)
+    }
+
+    /// Saves the result of a search `ply` plies below the root, always replacing the old entry.
+    pub fn store(
+        &self,
+        key: u64,
+        ply: u32,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+    ) {
+        let score = match score {
+            s if s > MATE_BOUND => s + ply as i32,
+            s if s < -MATE_BOUND => s - ply as i32,
+            s => s,
+        };
+        let data = Entry {
+            best_move,
+            score,
+            depth,
+            bound,
+        }
+        .pack();
+        let [stored, slot_data] = self.slot(key);
+        stored.store(key ^ data, Ordering::Relaxed);
+        slot_data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// Polyglot key of the position, with the variant state it leaves out (pockets, checks given,
+/// promoted pieces) mixed in.
+pub fn position_key(game: &Game) -> u64 {
+    let counts = game.pockets.iter().flatten().chain(&game.checks);
+    let extra = counts.fold(game.promoted, |acc, &n| {
+        acc.wrapping_mul(0x100000001B3) ^ n as u64
+    });
+    if extra == 0 {
+        return polyglot_key(game);
+    }
+    // splitmix64 finalizer, so that small counts still flip many bits
+    let mut z = extra.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ z >> 30).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ z >> 27).wrapping_mul(0x94D049BB133111EB);
+    polyglot_key(game) ^ z ^ z >> 31
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let key = 0x463B96181691FC9C;
+        assert_eq!(tt.probe(key), None);
+        tt.store(
+            key,
+            0,
+            7,
+            -35,
+            Bound::Lower,
+            Some((12, 4, Some(Piece::Knight))),
+        );
+        let entry = tt.probe(key).unwrap();
+        assert_eq!(entry.best_move, Some((12, 4, Some(Piece::Knight))));
+        assert_eq!(
+            (entry.score(0), entry.depth, entry.bound),
+            (-35, 7, Bound::Lower)
+        );
+        assert_eq!(tt.probe(key ^ 1), None);
+
+        // mate 5 plies from the root, found at a node 2 plies deep, is kept as 3 plies from it
+        tt.store(key, 2, 1, MATE - 5, Bound::Exact, None);
+        let entry = tt.probe(key).unwrap();
+        assert_eq!((entry.score(0), entry.score(4)), (MATE - 3, MATE - 7));
+        assert_eq!(entry.best_move, None);
+
+        tt.clear();
+        assert_eq!(tt.probe(key), None);
+    }
+
+    #[test]
+    fn keys() {
+        let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(position_key(&game), 0x463B96181691FC9C);
+        let crazyhouse = |fen| Game::new(fen, Some("crazyhouse".into())).unwrap();
+        assert_ne!(
+            position_key(&crazyhouse("4k3/8/8/8/8/8/8/4K3[N] w - - 0 1")),
+            position_key(&crazyhouse("4k3/8/8/8/8/8/8/4K3[n] w - - 0 1"))
+        );
+    }
+}
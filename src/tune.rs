@@ -0,0 +1,217 @@
+//! Texel tuning: fits the weights of `eval::Params` to game results. The evaluation of each
+//! position, squashed through a sigmoid, predicts the result, and gradient descent brings the
+//! mean squared error of those predictions down.
+
+use crate::eval::{terms, Params, PARAMS};
+use crate::gamestate::Game;
+
+/// A labelled position, reduced to what the evaluation sees of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    /// `eval::terms` of the position, from white's point of view.
+    pub terms: [i32; PARAMS],
+    /// 1 for a white win, 0.5 for a draw, 0 for a black win.
+    pub result: f64,
+}
+
+impl Sample {
+    pub fn new(game: &Game, result: f64) -> Self {
+        Sample {
+            terms: terms(game),
+            result,
+        }
+    }
+
+    /// Parses a FEN followed by the result, as `1-0`, `1/2-1/2` or `0-1`, or as a number,
+    /// bracketed or quoted as most data sets have it: `<fen> [0.5]`, `<fen> c9 "1-0";`.
+    /// Move counters may be left out.
+    pub fn parse(line: &str) -> Option<Sample> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 5 {
+            return None;
+        }
+        let label = fields[fields.len() - 1].trim_matches(|c| "[]\";".contains(c));
+        let result = match label {
+            "1-0" => 1.0,
+            "1/2-1/2" => 0.5,
+            "0-1" => 0.0,
+            number => number.parse().ok().filter(|r| (0.0..=1.0).contains(r))?,
+        };
+        let counters = fields[4..fields.len() - 1]
+            .iter()
+            .take(2)
+            .all(|field| field.parse::<u32>().is_ok());
+        let fen = match fields.len() {
+            7.. if counters => fields[..6].join(" "),
+            _ => format!("{} 0 1", fields[..4].join(" ")),
+        };
+        Some(Sample::new(&Game::from_fen(&fen), result))
+    }
+}
+
+/// Expected score of white at `score` centipawns; `k` sets how steep the curve is.
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+fn score(terms: &[i32; PARAMS], weights: &[f64; PARAMS]) -> f64 {
+    terms.iter().zip(weights).map(|(&t, w)| t as f64 * w).sum()
+}
+
+/// Mean squared error of the predictions of `params` over `samples`.
+pub fn error(samples: &[Sample], params: &Params, k: f64) -> f64 {
+    let weights = params.weights().map(f64::from);
+    error_with(samples, &weights, k)
+}
+
+fn error_with(samples: &[Sample], weights: &[f64; PARAMS], k: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|s| (s.result - sigmoid(score(&s.terms, weights), k)).powi(2))
+        .sum();
+    total / samples.len().max(1) as f64
+}
+
+/// The `k` that fits `params` best, to two decimals. Tuning keeps it fixed, so that the
+/// weights stay in centipawns instead of drifting together.
+pub fn best_k(samples: &[Sample], params: &Params) -> f64 {
+    let mut best = 1.0;
+    let mut step = 1.0;
+    while step >= 0.01 {
+        let around = [best - step, best, best + step];
+        best = around
+            .into_iter()
+            .filter(|&k| k > 0.0)
+            .min_by(|&a, &b| error(samples, params, a).total_cmp(&error(samples, params, b)))
+            .unwrap();
+        if best == around[1] {
+            step /= 10.0;
+        }
+    }
+    best
+}
+
+/// Settings of the descent. Steps follow Adam, which copes with gradients of very different
+/// sizes (a pawn more or less against a step of centrality).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuner {
+    pub k: f64,
+    pub iterations: u32,
+    /// Largest change of a weight per iteration, in centipawns.
+    pub rate: f64,
+}
+
+impl Tuner {
+    /// Runs the descent from `start`, calling `progress` with the iteration and error every
+    /// hundred iterations.
+    pub fn tune(
+        &self,
+        samples: &[Sample],
+        start: &Params,
+        mut progress: impl FnMut(u32, f64),
+    ) -> Params {
+        const BETA1: f64 = 0.9;
+        const BETA2: f64 = 0.999;
+        let mut weights = start.weights().map(f64::from);
+        let (mut m, mut v) = ([0.0; PARAMS], [0.0; PARAMS]);
+        // d sigmoid / d score = sigmoid * (1 - sigmoid) * slope
+        let slope = self.k * std::f64::consts::LN_10 / 400.0;
+        for iteration in 1..=self.iterations {
+            let mut gradient = [0.0; PARAMS];
+            for sample in samples {
+                let predicted = sigmoid(score(&sample.terms, &weights), self.k);
+                let delta = (predicted - sample.result) * predicted * (1.0 - predicted);
+                for (g, &t) in gradient.iter_mut().zip(&sample.terms) {
+                    *g += delta * t as f64;
+                }
+            }
+            for j in 0..PARAMS {
+                let g = 2.0 * slope * gradient[j] / samples.len().max(1) as f64;
+                m[j] = BETA1 * m[j] + (1.0 - BETA1) * g;
+                v[j] = BETA2 * v[j] + (1.0 - BETA2) * g * g;
+                let m_hat = m[j] / (1.0 - BETA1.powi(iteration as i32));
+                let v_hat = v[j] / (1.0 - BETA2.powi(iteration as i32));
+                weights[j] -= self.rate * m_hat / (v_hat.sqrt() + 1e-8);
+            }
+            if iteration % 100 == 0 {
+                progress(iteration, error_with(samples, &weights, self.k));
+            }
+        }
+        Params::from_weights(weights.map(|w| w.round() as i32))
+    }
+}
+
+/// `params` as Rust source, ready to replace `eval::DEFAULT_PARAMS`.
+pub fn to_rust(params: &Params) -> String {
+    format!(
+        "pub const DEFAULT_PARAMS: Params = Params {{\n    material: {:?},\n    \
+         knight_centrality: {},\n    bishop_centrality: {},\n    pawn_advance: {},\n}};\n",
+        params.material, params.knight_centrality, params.bishop_centrality, params.pawn_advance
+    )
+}
+
+pub fn to_json(params: &Params) -> String {
+    serde_json::to_string_pretty(params).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::DEFAULT_PARAMS;
+
+    #[test]
+    fn parse() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let sample = Sample::parse(&format!("{start} [0.5]")).unwrap();
+        assert_eq!(sample.result, 0.5);
+        assert_eq!(sample.terms, [0; PARAMS]);
+        let epd = "4k3/8/8/8/4P3/2N5/8/4K3 w - - c9 \"1-0\";";
+        assert_eq!(Sample::parse(epd).unwrap().result, 1.0);
+        assert_eq!(Sample::parse(epd).unwrap().terms[..2], [1, 1]);
+        assert_eq!(
+            Sample::parse("4k3/8/8/8/8/8/8/4K3 b - - 0-1")
+                .unwrap()
+                .result,
+            0.0
+        );
+        assert_eq!(Sample::parse(&format!("{start} 2.0")), None);
+        assert_eq!(Sample::parse(&format!("{start} draw")), None);
+        assert_eq!(Sample::parse("1-0"), None);
+    }
+
+    #[test]
+    fn knights_win() {
+        // an extra knight wins, an extra pawn on the second rank only draws
+        let mut samples = vec![];
+        for (fen, result) in [
+            ("4k3/8/8/8/8/2N5/8/4K3 w - - 0 1", 1.0),
+            ("4k3/8/8/8/8/8/3N4/4K3 b - - 0 1", 1.0),
+            ("4k3/8/5n2/8/8/8/8/4K3 w - - 0 1", 0.0),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", 0.5),
+            ("4k3/2p5/8/8/8/8/8/4K3 b - - 0 1", 0.5),
+        ] {
+            samples.push(Sample::new(&Game::from_fen(fen), result));
+        }
+        let start = Params {
+            material: [100, 100, 330, 500, 900],
+            ..DEFAULT_PARAMS
+        };
+        let k = best_k(&samples, &start);
+        assert!(k > 0.0);
+        let tuner = Tuner {
+            k,
+            iterations: 500,
+            rate: 5.0,
+        };
+        let mut reports = 0;
+        let tuned = tuner.tune(&samples, &start, |_, _| reports += 1);
+        assert_eq!(reports, 5);
+        assert!(error(&samples, &tuned, k) < error(&samples, &start, k));
+        assert!(tuned.material[1] > 2 * tuned.material[0]);
+
+        let rust = to_rust(&tuned);
+        assert!(rust.starts_with("pub const DEFAULT_PARAMS: Params = Params {"));
+        let json: Params = serde_json::from_str(&to_json(&tuned)).unwrap();
+        assert_eq!(json, tuned);
+    }
+}
@@ -0,0 +1,602 @@
+use crate::bitboards::*;
+use crate::castling::*;
+use crate::consts::*;
+use crate::gamestate::*;
+use crate::letterbox::{Color, Piece};
+use crate::moves::*;
+use crate::rays::*;
+
+/// How a game ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Winner(Color),
+    Draw,
+}
+
+/// A move that was just played, as seen by `Variant::after_move`.
+pub struct PlayedMove {
+    pub from: usize,
+    pub to: usize,
+    pub piece: Piece,
+    /// Square and kind of the captured piece. The square is `to`, or the passed pawn for
+    /// en passant.
+    pub captured: Option<(usize, Piece)>,
+    /// Whether the captured piece had been promoted from a pawn.
+    pub captured_promoted: bool,
+    pub castling: Option<CastlingPath>,
+    pub promotion: Option<Piece>,
+}
+
+/// Rules of a chess variant, hooked around move generation and move making.
+/// Every hook defaults to standard chess.
+pub trait Variant: Sync {
+    /// Name the variant is selected by: lichess variant keys in lowercase (`"kingofthehill"`).
+    fn name(&self) -> &'static str;
+
+    /// Legal moves of the side to move, in the `gen_all_moves` encoding.
+    fn legal_moves(&self, game: &Game) -> [u64; 64] {
+        gen_all_moves(game.turn, &game.bitboards, game.enpassant, game.castling)
+    }
+
+    /// Runs after the board update of every move, once the turn has passed.
+    /// Squares the variant empties are pushed to `removed`.
+    fn after_move(&self, _game: &mut Game, _played: &PlayedMove, _removed: &mut Vec<usize>) {}
+
+    /// Whether the game is over. Called once `game.legal_moves` is up to date.
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        mate_or_stalemate(game)
+    }
+
+    /// Squares the side to move may drop pocket pieces on.
+    fn legal_drops(&self, _game: &Game) -> u64 {
+        0
+    }
+
+    /// Whether captured pieces go to the capturer's pocket, to be shown in FENs.
+    fn has_pockets(&self) -> bool {
+        false
+    }
+
+    /// Whether pawns may promote to a king (promotion code 5).
+    fn king_promotion(&self) -> bool {
+        false
+    }
+}
+
+pub static VARIANTS: [&dyn Variant; 7] = [
+    &Standard,
+    &KingOfTheHill,
+    &ThreeCheck,
+    &Horde,
+    &Antichess,
+    &Atomic,
+    &Crazyhouse,
+];
+
+pub fn variant_by_name(name: &str) -> Option<&'static dyn Variant> {
+    VARIANTS.iter().find(|v| v.name() == name).copied()
+}
+
+fn mate_or_stalemate(game: &Game) -> Option<Outcome> {
+    if game.legal_moves.iter().any(|&moves| moves != 0) || game.has_drops() {
+        None
+    } else if game.is_check() {
+        Some(Outcome::Winner(!game.turn))
+    } else {
+        Some(Outcome::Draw)
+    }
+}
+
+fn sides(game: &Game) -> (u64, u64) {
+    (
+        game.bitboards.color(game.turn),
+        game.bitboards.color(!game.turn),
+    )
+}
+
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "standard"
+    }
+}
+
+/// A king reaching one of the four center squares wins.
+pub struct KingOfTheHill;
+
+const CENTER: u64 = 1 << 27 | 1 << 28 | 1 << 35 | 1 << 36;
+
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "kingofthehill"
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let hill = game.bitboards.kings & CENTER;
+        if hill & game.bitboards.whites != 0 {
+            Some(Outcome::Winner(Color::White))
+        } else if hill & game.bitboards.blacks != 0 {
+            Some(Outcome::Winner(Color::Black))
+        } else {
+            mate_or_stalemate(game)
+        }
+    }
+}
+
+/// Giving check for the third time wins.
+pub struct ThreeCheck;
+
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "threecheck"
+    }
+
+    fn after_move(&self, game: &mut Game, _played: &PlayedMove, _removed: &mut Vec<usize>) {
+        if game.is_check() {
+            game.checks[(!game.turn).index()] += 1;
+        }
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        if game.checks[1] >= 3 {
+            Some(Outcome::Winner(Color::White))
+        } else if game.checks[0] >= 3 {
+            Some(Outcome::Winner(Color::Black))
+        } else {
+            mate_or_stalemate(game)
+        }
+    }
+}
+
+/// White has a horde of pawns and no king, and loses once all of it is captured.
+/// Pawns on the first rank may advance two squares.
+pub struct Horde;
+
+impl Variant for Horde {
+    fn name(&self) -> &'static str {
+        "horde"
+    }
+
+    fn legal_moves(&self, game: &Game) -> [u64; 64] {
+        let mut moves = gen_all_moves(game.turn, &game.bitboards, game.enpassant, game.castling);
+        let (friends, foes) = sides(game);
+        let consts = if game.turn == Color::White {
+            Consts::WHITE
+        } else {
+            Consts::BLACK
+        };
+        if friends & game.bitboards.kings == 0 {
+            let free = !(friends | foes);
+            apply!(friends & game.bitboards.pawns & consts.first_rank, i -> {
+                let single = shift_forward(1 << i, game.turn) & free;
+                moves[i] |= shift_forward(single, game.turn) & free;
+            });
+        }
+        moves
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        if game.bitboards.whites == 0 {
+            Some(Outcome::Winner(Color::Black))
+        } else if game.bitboards.blacks == 0 {
+            Some(Outcome::Winner(Color::White))
+        } else {
+            mate_or_stalemate(game)
+        }
+    }
+}
+
+fn shift_forward(bb: u64, color: Color) -> u64 {
+    if color == Color::White {
+        bb >> 8
+    } else {
+        bb << 8
+    }
+}
+
+/// Losing all pieces, or having no move, wins. Captures are compulsory and the king is an
+/// ordinary piece: there is no check and no castling.
+pub struct Antichess;
+
+impl Variant for Antichess {
+    fn name(&self) -> &'static str {
+        "antichess"
+    }
+
+    fn legal_moves(&self, game: &Game) -> [u64; 64] {
+        let mut moves = gen_pseudo_moves(game.turn, &game.bitboards, game.enpassant);
+        let (friends, foes) = sides(game);
+        let enpassant = game.enpassant.map_or(0, |sq| 1 << sq);
+        let mut captures = [0; 64];
+        let mut must_capture = false;
+        apply!(friends, i -> {
+            let targets = if game.bitboards.pawns & 1 << i != 0 {
+                foes | enpassant
+            } else {
+                foes
+            };
+            captures[i] = moves[i] & targets;
+            must_capture |= captures[i] != 0;
+        });
+        if must_capture {
+            moves = captures;
+        }
+        moves
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let (friends, _) = sides(game);
+        if friends == 0 || game.legal_moves.iter().all(|&moves| moves == 0) {
+            Some(Outcome::Winner(game.turn))
+        } else {
+            None
+        }
+    }
+
+    fn king_promotion(&self) -> bool {
+        true
+    }
+}
+
+/// Captures explode: the capturing piece and every non-pawn piece next to the capture square
+/// are removed along with the captured one. Blowing up the enemy king wins, so kings cannot
+/// capture, and kings standing next to each other cannot be checked.
+pub struct Atomic;
+
+impl Atomic {
+    /// Enemy pieces giving check to a king on `square`.
+    fn king_attackers(game: &Game, square: usize, attackers: u64, occupancy: u64) -> u64 {
+        let kings = game.bitboards.kings & attackers;
+        if kings == 0 || KING_MOVES[square] & kings != 0 {
+            0
+        } else {
+            attackers_to(square, attackers, &game.bitboards, occupancy)
+        }
+    }
+
+    /// Castling moves of the king on `k_sq`, tested against atomic checks.
+    fn castling_moves(game: &Game, k_sq: usize) -> u64 {
+        let (friends, foes) = sides(game);
+        let consts = if game.turn == Color::White {
+            Consts::WHITE
+        } else {
+            Consts::BLACK
+        };
+        let occupancy = friends | foes;
+        let mut moves = 0;
+        if 1 << k_sq & consts.first_rank == 0 {
+            return 0;
+        }
+        apply!(game.castling & friends & game.bitboards.rooks & consts.first_rank, r -> {
+            let path = CastlingPath::new(k_sq, r);
+            let occ = occupancy ^ 1 << k_sq ^ 1 << r;
+            // the destination is tested above, with the rook already beside the king
+            let walk = path.safe & !(1 << path.king_to) | 1 << k_sq;
+            let mut safe = path.empty & occ == 0
+                && Atomic::king_attackers(game, path.king_to, foes, occ | 1 << path.rook_to) == 0;
+            apply!(walk, sq -> {
+                safe &= Atomic::king_attackers(game, sq, foes, occupancy ^ 1 << k_sq) == 0;
+            });
+            if safe {
+                moves |= 1 << path.target();
+            }
+        });
+        moves
+    }
+}
+
+impl Variant for Atomic {
+    fn name(&self) -> &'static str {
+        "atomic"
+    }
+
+    fn legal_moves(&self, game: &Game) -> [u64; 64] {
+        let mut moves = gen_pseudo_moves(game.turn, &game.bitboards, game.enpassant);
+        let (friends, foes) = sides(game);
+        apply!(friends & game.bitboards.kings, k -> {
+            moves[k] &= !foes;
+            moves[k] |= Atomic::castling_moves(game, k);
+        });
+
+        // Explosions reach too far for pin masks: play every move out on a copy.
+        // The promotion piece makes no difference to the blast, any will do.
+        for (from, moves) in moves.iter_mut().enumerate() {
+            apply!(*moves, to -> {
+                let mut after = game.clone();
+                after.play(from, to, game.is_promotion(from, to).then_some(Piece::Queen));
+                let (ours, theirs) = if game.turn == Color::White {
+                    (after.bitboards.whites, after.bitboards.blacks)
+                } else {
+                    (after.bitboards.blacks, after.bitboards.whites)
+                };
+                let our_king = ours & after.bitboards.kings;
+                let legal = our_king != 0
+                    && (theirs & after.bitboards.kings == 0
+                        || Atomic::king_attackers(&after, bsf(our_king) as usize, theirs, ours | theirs)
+                            == 0);
+                if !legal {
+                    *moves ^= 1 << to;
+                }
+            });
+        }
+        moves
+    }
+
+    fn after_move(&self, game: &mut Game, played: &PlayedMove, removed: &mut Vec<usize>) {
+        if played.captured.is_none() {
+            return;
+        }
+        let bitboards = &mut game.bitboards;
+        let occupancy = bitboards.whites | bitboards.blacks;
+        let blast = KING_MOVES[played.to] & occupancy & !bitboards.pawns | 1 << played.to;
+        apply!(blast, sq -> {
+            bitboards.remove(sq);
+            removed.push(sq);
+        });
+        game.castling &= !blast;
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        if game.bitboards.kings & game.bitboards.whites == 0 {
+            Some(Outcome::Winner(Color::Black))
+        } else if game.bitboards.kings & game.bitboards.blacks == 0 {
+            Some(Outcome::Winner(Color::White))
+        } else if game.legal_moves.iter().any(|&moves| moves != 0) {
+            None
+        } else {
+            let (friends, foes) = sides(game);
+            let king = bsf(friends & game.bitboards.kings) as usize;
+            if Atomic::king_attackers(game, king, foes, friends | foes) != 0 {
+                Some(Outcome::Winner(!game.turn))
+            } else {
+                Some(Outcome::Draw)
+            }
+        }
+    }
+}
+
+/// Captured pieces change sides and go to the capturer's pocket, from which they can be
+/// dropped on any empty square instead of moving. Pawns are not dropped on the back ranks,
+/// and promoted pieces go back to the pocket as pawns.
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "crazyhouse"
+    }
+
+    fn legal_drops(&self, game: &Game) -> u64 {
+        let (friends, foes) = sides(game);
+        let occupancy = friends | foes;
+        let king = bsf(friends & game.bitboards.kings) as usize;
+        let checkers = attackers_to(king, foes, &game.bitboards, occupancy);
+        match checkers.count_ones() {
+            0 => !occupancy,
+            // a drop can only block a slider
+            1 => {
+                let checker = bsf(checkers) as usize;
+                DIAGONALS_INTERSECT[checker][king] | RANKFILES_INTERSECT[checker][king]
+            }
+            _ => 0,
+        }
+    }
+
+    fn after_move(&self, game: &mut Game, played: &PlayedMove, _removed: &mut Vec<usize>) {
+        if let Some((_, piece)) = played.captured {
+            let piece = if played.captured_promoted {
+                Piece::Pawn
+            } else {
+                piece
+            };
+            game.pockets[(!game.turn).index()][piece as usize - 1] += 1;
+        }
+    }
+
+    fn has_pockets(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perft(variant: &'static dyn Variant, fen: &str, nodes: &[u64]) {
+        let game = Game::with_variant(fen, variant);
+        for (depth, &nodes) in nodes.iter().enumerate() {
+            let depth = depth as u32 + 1;
+            assert_eq!(game.perft(depth), nodes, "{fen} depth {depth}");
+        }
+    }
+
+    #[test]
+    fn by_name() {
+        for variant in VARIANTS {
+            assert_eq!(
+                variant_by_name(variant.name()).unwrap().name(),
+                variant.name()
+            );
+        }
+        assert!(variant_by_name("chess").is_none());
+    }
+
+    #[test]
+    fn standard_outcomes() {
+        let mate = Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert_eq!(mate.outcome, Some(Outcome::Winner(Color::Black)));
+        let stalemate = Game::from_fen("k7/8/1Q6/8/8/8/8/7K b - - 0 1");
+        assert_eq!(stalemate.outcome, Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        let mut game = Game::with_variant("8/8/8/8/8/3K1k2/8/8 w - - 0 1", &KingOfTheHill);
+        assert!(game.make_move(43, 35, None).is_some());
+        assert_eq!(game.outcome, Some(Outcome::Winner(Color::White)));
+        assert_eq!(game.legal_moves, [0; 64]);
+        perft(
+            &KingOfTheHill,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281],
+        );
+    }
+
+    #[test]
+    fn three_check() {
+        let mut game = Game::with_variant(
+            "rnbqkbnr/ppp2ppp/8/3pp3/8/4P3/PPPP1PPP/RNBQKBNR w KQkq - 0 3",
+            &ThreeCheck,
+        );
+        // Bb5+ c6 Bxc6+ Nxc6 Qh5 Nf6 Qxf7+
+        for (from, to) in [
+            (61, 25),
+            (10, 18),
+            (25, 18),
+            (1, 18),
+            (59, 31),
+            (6, 21),
+            (31, 13),
+        ] {
+            assert!(game.make_move(from, to, None).is_some());
+        }
+        assert_eq!(game.checks, [0, 3]);
+        assert_eq!(game.outcome, Some(Outcome::Winner(Color::White)));
+        perft(
+            &ThreeCheck,
+            // one check left each
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 1+1 0 1",
+            &[26, 562, 13410],
+        );
+    }
+
+    #[test]
+    fn horde() {
+        perft(
+            &Horde,
+            "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+            &[8, 128, 1274, 23310],
+        );
+        // first rank pawns advance two squares without an en passant square
+        let mut game = Game::with_variant("4k3/8/8/8/8/8/8/P7 w - - 0 1", &Horde);
+        assert!(game.make_move(56, 40, None).is_some());
+        assert_eq!(game.enpassant, None);
+    }
+
+    #[test]
+    fn antichess() {
+        perft(
+            &Antichess,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            &[20, 400, 8067, 153299],
+        );
+        // captures are compulsory, even for the king
+        let game = Game::with_variant("8/8/8/8/8/8/3p4/4K3 w - - 0 1", &Antichess);
+        assert_eq!(game.legal_moves[60], 1 << 51);
+        // promoting to a king
+        let game = Game::with_variant("8/P7/8/8/8/8/8/7k w - - 0 1", &Antichess);
+        assert_eq!(game.perft(1), 5);
+        // running out of pieces wins
+        let mut game = Game::with_variant("8/8/8/8/8/8/3p4/4K3 b - - 0 1", &Antichess);
+        assert!(game.make_move(51, 60, Some(Piece::Knight)).is_some());
+        assert_eq!(game.outcome, Some(Outcome::Winner(Color::White)));
+    }
+
+    #[test]
+    fn atomic() {
+        perft(
+            &Atomic,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197326],
+        );
+        // Nxf7 blows up the black king along with the f7 pawn and the knight
+        let mut game = Game::with_variant(
+            "rnbqkb1r/pppppppp/8/6N1/8/8/PPPPPPPP/RNBQKB1R w KQkq - 0 1",
+            &Atomic,
+        );
+        let response = game.make_move(30, 13, None).unwrap();
+        assert_eq!(game.outcome, Some(Outcome::Winner(Color::White)));
+        assert_eq!(game.bitboards.kings & game.bitboards.blacks, 0);
+        assert_eq!(game.bitboards.pawns & 1 << 13, 0);
+        assert_eq!(response.captured, Some((13, Piece::Pawn)));
+        assert!(response.removed.contains(&4));
+        assert!(response.removed.contains(&13));
+        assert_eq!(response.san, "Nxf7");
+        // kings do not capture, and connected kings cannot be checked
+        let game = Game::with_variant("8/8/8/3kK3/8/8/8/3R4 b - - 0 1", &Atomic);
+        assert!(game.legal_moves[27] != 0);
+        assert_eq!(game.legal_moves[27] & 1 << 28, 0);
+    }
+
+    #[test]
+    fn crazyhouse() {
+        perft(
+            &Crazyhouse,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            &[20, 400, 8902, 197281],
+        );
+        perft(
+            &Crazyhouse,
+            "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1",
+            &[42, 1347, 58057],
+        );
+        perft(
+            &Crazyhouse,
+            "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+            &[301, 75353],
+        );
+    }
+
+    #[test]
+    fn crazyhouse_pockets() {
+        let mut game = Game::with_variant(
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR[] w KQkq - 0 2",
+            &Crazyhouse,
+        );
+        // exd5 puts a pawn in white's pocket
+        assert!(game.make_move(36, 27, None).is_some());
+        assert_eq!(game.pockets, [[0; 5], [1, 0, 0, 0, 0]]);
+        assert!(game.make_move(3, 27, None).is_some());
+        assert_eq!(game.pockets, [[1, 0, 0, 0, 0], [1, 0, 0, 0, 0]]);
+        // pawns are not dropped on the back ranks
+        assert_eq!(game.drop_squares(Piece::Pawn) & 0xFF000000000000FF, 0);
+        assert_eq!(game.drop_squares(Piece::Knight), 0);
+        let (piece, to) = game.parse_uci_drop("P@e6").unwrap();
+        assert!(game.drop_piece(piece, to).is_some());
+        assert_eq!(game.uci_drop(piece, to), "P@e6");
+        assert_eq!(
+            game.to_fen(),
+            "rnb1kbnr/ppp1pppp/4P3/3q4/8/8/PPPP1PPP/RNBQKBNR[p] b KQkq - 0 3"
+        );
+
+        // a promoted queen goes back to the pocket as a pawn
+        let mut game = Game::with_variant("4k3/8/8/8/8/8/8/q~2R1K2[] w - - 0 1", &Crazyhouse);
+        assert_eq!(game.promoted, 1 << 56);
+        assert!(game.make_move(59, 56, None).is_some());
+        assert_eq!(game.pockets, [[0; 5], [1, 0, 0, 0, 0]]);
+        assert_eq!(game.promoted, 0);
+
+        let fen = "r2q1rk1/ppp2ppp/2n1pn2/3p4/1b1P4/2N1BN2/PPPQ1PPP/R3KB1R~/NBbp w KQ - 4 12";
+        let game = Game::with_variant(fen, &Crazyhouse);
+        assert_eq!(game.pockets, [[1, 0, 1, 0, 0], [0, 1, 1, 0, 0]]);
+        assert_eq!(
+            game.to_fen(),
+            "r2q1rk1/ppp2ppp/2n1pn2/3p4/1b1P4/2N1BN2/PPPQ1PPP/R3KB1R~[NBpb] w KQ - 4 12"
+        );
+    }
+
+    #[test]
+    fn drops_block_checks() {
+        // a rook check can be blocked on the file, a knight check not at all
+        let game = Game::with_variant("4k3/8/8/8/8/8/8/4RK2[n] b - - 0 1", &Crazyhouse);
+        assert_eq!(game.drops, RANKFILES_INTERSECT[4][60]);
+        let game = Game::with_variant("4k3/8/3N4/8/8/8/8/5K2[q] b - - 0 1", &Crazyhouse);
+        assert_eq!(game.drops, 0);
+        // a drop is a way out of mate, but pawns cannot block on the back rank
+        let game = Game::with_variant("k6R/8/1K6/8/8/8/8/8[n] b - - 0 1", &Crazyhouse);
+        assert_eq!(game.outcome, None);
+        assert_eq!(game.drops, 0x7E);
+        let game = Game::with_variant("k6R/8/1K6/8/8/8/8/8[p] b - - 0 1", &Crazyhouse);
+        assert_eq!(game.outcome, Some(Outcome::Winner(Color::White)));
+    }
+}